@@ -1,5 +1,7 @@
+#![allow(clippy::drop_non_drop)]
+
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use colored::Colorize;
 use console::Term;
 use fbxcel::low::FbxVersion;
@@ -23,6 +25,150 @@ struct Args {
 
     #[arg(short, long, help = "Output folder for FBX files")]
     output: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = ColorSpace::Srgb, help = "Color space to write COLOR_0 vertex colors in")]
+    vertex_colors: ColorSpace,
+
+    #[arg(long, value_enum, default_value_t = TextureMode::Embed, help = "Embed textures in the FBX or write them next to it")]
+    textures: TextureMode,
+
+    #[arg(long, help = "Folder for external textures, relative to the output folder [default: <stem>.fbm]")]
+    texture_dir: Option<PathBuf>,
+
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), help = "Keep at most N bone influences per vertex, strongest first")]
+    max_influences: Option<u32>,
+
+    #[arg(long, value_enum, default_value_t = GeometryMode::Shared, help = "Share one Geometry between the Models instancing a mesh, or give each Model its own copy")]
+    geometry: GeometryMode,
+
+    #[arg(long, value_enum, default_value_t = InstancingMode::Expand, help = "Write EXT_mesh_gpu_instancing instances as child Models or bake them into one mesh")]
+    instancing: InstancingMode,
+
+    #[arg(long, value_enum, default_value_t = RotationOrder::Xyz, help = "Euler rotation order for Models and rotation curves")]
+    rotation_order: RotationOrder,
+
+    #[arg(long, value_name = "FPS", value_parser = clap::value_parser!(u32).range(1..), help = "Resample interpolated rotation channels at FPS keys per second")]
+    resample_rotations: Option<u32>,
+
+    #[arg(long, value_enum, default_value_t = AnimationMode::All, help = "Write animations as separate takes, one merged take, or one FBX each")]
+    animations: AnimationMode,
+
+    #[arg(long, value_name = "FACTOR", default_value_t = 100.0, help = "FBX light Intensity per glTF candela (point/spot) or lux (directional)")]
+    light_intensity_scale: f64,
+
+    #[arg(long, value_name = "SCENE", default_value = "default", help = "Scene to export: an index, a name, `default` or `all` (one FBX per scene)")]
+    scene: SceneSelection,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum TextureMode {
+    /// Store image files inside the FBX
+    Embed,
+    /// Write image files to a folder and reference them by path
+    External,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ColorSpace {
+    /// Write glTF's linear values unchanged
+    Linear,
+    /// Encode RGB with the sRGB transfer curve, as most DCC tools display FBX colors
+    Srgb,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum GeometryMode {
+    /// One Geometry per glTF mesh (and skin), connected to every Model that instances it
+    Shared,
+    /// A copy of the Geometry for every Model, for engines that mishandle shared geometry
+    Duplicate,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum InstancingMode {
+    /// One child Model per instance, all sharing the node's Geometry
+    Expand,
+    /// Bake every instance into a single mesh on the node
+    Collapse,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum AnimationMode {
+    /// One AnimationStack per glTF animation
+    All,
+    /// A single AnimationStack with the clips end to end, plus a <stem>.clips.csv clip list
+    Merge,
+    /// One FBX per glTF animation, named <stem>@<animation>.fbx
+    Split,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum RotationOrder {
+    /// Rotate about X, then Y, then Z (the FBX default)
+    Xyz,
+    /// Rotate about X, then Z, then Y
+    Xzy,
+    /// Rotate about Y, then Z, then X
+    Yzx,
+    /// Rotate about Y, then X, then Z
+    Yxz,
+    /// Rotate about Z, then X, then Y
+    Zxy,
+    /// Rotate about Z, then Y, then X
+    Zyx,
+}
+
+// Which glTF scene(s) to export; `default` and `all` win over scenes with those names,
+// and a number is read as an index rather than a name
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SceneSelection {
+    // The default scene, else the first one, else every parentless node
+    Default,
+    // Every scene, one FBX each
+    All,
+    Index(usize),
+    Name(String),
+}
+
+impl std::str::FromStr for SceneSelection {
+    type Err = std::convert::Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
+            "default" => SceneSelection::Default,
+            "all" => SceneSelection::All,
+            _ => match value.parse() {
+                Ok(index) => SceneSelection::Index(index),
+                Err(_) => SceneSelection::Name(value.to_string()),
+            },
+        })
+    }
+}
+
+impl RotationOrder {
+    // Axis indices in the order the rotations are applied
+    fn axes(self) -> [usize; 3] {
+        match self {
+            RotationOrder::Xyz => [0, 1, 2],
+            RotationOrder::Xzy => [0, 2, 1],
+            RotationOrder::Yzx => [1, 2, 0],
+            RotationOrder::Yxz => [1, 0, 2],
+            RotationOrder::Zxy => [2, 0, 1],
+            RotationOrder::Zyx => [2, 1, 0],
+        }
+    }
+
+    // Value of the Model's RotationOrder enum property (FbxEuler::EOrder)
+    fn fbx_value(self) -> i32 {
+        match self {
+            RotationOrder::Xyz => 0,
+            RotationOrder::Xzy => 1,
+            RotationOrder::Yzx => 2,
+            RotationOrder::Yxz => 3,
+            RotationOrder::Zxy => 4,
+            RotationOrder::Zyx => 5,
+        }
+    }
 }
 
 fn print_banner() {
@@ -180,36 +326,51 @@ fn main() -> Result<()> {
             .and_then(|n| n.to_str())
             .unwrap_or("unknown");
         
-        let input_size = fs::metadata(path)
-            .map(|m| m.len())
-            .unwrap_or(0);
-        
         pb.set_message(format!("{} {}", 
             format!("[{}/{}]", index + 1, total_files).bright_black().bold(),
             file_name.bright_white().bold()
         ));
         
         let file_start = Instant::now();
-        match convert_glb_to_fbx(path, &args.output) {
-            Ok(output_path) => {
-                let output_size = fs::metadata(&output_path)
-                    .map(|m| m.len())
-                    .unwrap_or(0);
+        match convert_glb_to_fbx(path, &args) {
+            Ok((output_paths, stats)) => {
+                let output_size: u64 = output_paths.iter()
+                    .map(|path| fs::metadata(path).map(|m| m.len()).unwrap_or(0))
+                    .sum();
                 total_output_size += output_size;
                 
                 let duration = file_start.elapsed();
-                let output_name = output_path.file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("unknown");
+                // --animations split writes one FBX per animation, merge adds the clip list
+                let output_name = output_paths.iter()
+                    .map(|path| path.file_name().and_then(|n| n.to_str()).unwrap_or("unknown"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
                 
-                pb.println(format!("    {} {} → {} {} {} {}",
+                let clamped_vertices: usize = stats.clamped_vertices.values().sum();
+                let clamped = if clamped_vertices > 0 {
+                    format!(" {} {}",
+                        "│".bright_black(),
+                        format!("{} {} clamped",
+                            clamped_vertices,
+                            if clamped_vertices == 1 { "vertex" } else { "vertices" }
+                        ).bright_magenta()
+                    )
+                } else {
+                    String::new()
+                };
+
+                pb.println(format!("    {} {} → {} {} {} {}{}",
                     "✓".green().bold(),
                     file_name.bright_white(),
                     output_name.bright_cyan(),
                     format!("({})", format_file_size(output_size)).bright_black(),
                     "│".bright_black(),
-                    format!("{}ms", duration.as_millis()).bright_yellow()
+                    format!("{}ms", duration.as_millis()).bright_yellow(),
+                    clamped
                 ));
+                for warning in &stats.warnings {
+                    pb.println(format!("      {} {}", "⚠".yellow().bold(), warning.bright_yellow()));
+                }
                 converted_count += 1;
             }
             Err(e) => {
@@ -316,41 +477,302 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn convert_glb_to_fbx(input_path: &Path, output_dir: &Path) -> Result<PathBuf> {
+// Per-file notes gathered while writing, reported under the file's output line
+#[derive(Default)]
+struct ConversionStats {
+    warnings: Vec<String>,
+    // Vertices that had more bone influences than --max-influences allows, by the node owning
+    // their Geometry, so a Geometry written for several scenes counts once
+    clamped_vertices: std::collections::HashMap<usize, usize>,
+}
+
+fn convert_glb_to_fbx(input_path: &Path, args: &Args) -> Result<(Vec<PathBuf>, ConversionStats)> {
     let file_stem = input_path
         .file_stem()
         .context("Failed to get file stem")?
         .to_str()
         .context("Invalid UTF-8 in filename")?;
 
-    let output_path = output_dir.join(format!("{}.fbx", file_stem));
-
-    let (gltf, buffers, _images) = gltf::import(input_path)
+    // gltf rejects files that require extensions it does not know, EXT_mesh_gpu_instancing among them
+    let data = fs::read(input_path)
+        .context("Failed to read GLB file")?;
+    let gltf::Gltf { document, blob } = gltf::Gltf::from_slice_without_validation(&data)
         .context("Failed to load GLB file")?;
+    let mut json = document.into_json();
+    json.extensions_required.retain(|extension| extension != EXT_MESH_GPU_INSTANCING);
+    let gltf = Document::from_json(json)
+        .context("Failed to load GLB file")?;
+    let buffers = gltf::import_buffers(&gltf, input_path.parent(), blob)
+        .context("Failed to load GLB buffers")?;
+
+    // FBX wants the encoded PNG/JPEG files, not the decoded pixels
+    let mut images = load_image_files(&gltf, &buffers, input_path)?;
+    if args.textures == TextureMode::External && !images.is_empty() {
+        write_external_images(&mut images, file_stem, args)?;
+    }
+
+    let mut stats = ConversionStats::default();
+    let owners = geometry_owners(&gltf, args);
+    let animations: Vec<AnimationClip> = gltf.animations()
+        .map(|animation| convert_animation(&animation, &buffers, &owners, args, &mut stats))
+        .collect();
+
+    // (scene, output file stem); only --scene all adds the scene to the file names
+    let scenes: Vec<(Option<gltf::Scene>, String)> = match &args.scene {
+        SceneSelection::Default => {
+            vec![(gltf.default_scene().or_else(|| gltf.scenes().next()), file_stem.to_string())]
+        }
+        SceneSelection::Index(index) => {
+            let scene = gltf.scenes().nth(*index).with_context(|| {
+                format!("Scene {} not found; the file has {} scene(s)", index, gltf.scenes().count())
+            })?;
+            vec![(Some(scene), file_stem.to_string())]
+        }
+        SceneSelection::Name(name) => {
+            let scene = gltf.scenes().find(|scene| scene.name() == Some(name.as_str()))
+                .with_context(|| format!("No scene named '{}'", name))?;
+            vec![(Some(scene), file_stem.to_string())]
+        }
+        SceneSelection::All if gltf.scenes().len() > 0 => {
+            let mut used_names: Vec<String> = Vec::new();
+            gltf.scenes()
+                .map(|scene| {
+                    let name = scene.name().map_or_else(|| scene.index().to_string(), str::to_string);
+                    let name = unique_file_name(&name, &mut used_names);
+                    (Some(scene), format!("{}_{}", file_stem, name))
+                })
+                .collect()
+        }
+        SceneSelection::All => vec![(None, file_stem.to_string())],
+    };
+
+    let mut output_paths = Vec::new();
+    for (scene, stem) in &scenes {
+        let exported = exported_nodes(&gltf, scene.as_ref());
+        // Clips animating none of the scene's nodes would come out empty
+        let clips: Vec<AnimationClip> = animations.iter()
+            .filter(|clip| clip.curve_nodes.iter().any(|curve_node| exported[curve_node.node]))
+            .cloned()
+            .collect();
+
+        // (output file, animations it carries)
+        let mut clip_list: Option<PathBuf> = None;
+        let outputs: Vec<(PathBuf, Vec<AnimationClip>)> = match args.animations {
+            AnimationMode::Split if !clips.is_empty() => {
+                let mut used_names: Vec<String> = Vec::new();
+                clips.iter()
+                    .map(|clip| {
+                        // Unity's <model>@<animation> naming
+                        let name = unique_file_name(&clip.name, &mut used_names);
+                        (args.output.join(format!("{}@{}.fbx", stem, name)), vec![clip.clone()])
+                    })
+                    .collect()
+            }
+            AnimationMode::Merge if !clips.is_empty() => {
+                let (merged, clip_ranges) = merge_animation_clips(clips, stem);
+                let clip_list_path = args.output.join(format!("{}.clips.csv", stem));
+                write_clip_list(&clip_list_path, &clip_ranges)?;
+                clip_list = Some(clip_list_path);
+                vec![(args.output.join(format!("{}.fbx", stem)), vec![merged])]
+            }
+            _ => vec![(args.output.join(format!("{}.fbx", stem)), clips)],
+        };
+
+        for (index, (output_path, clips)) in outputs.iter().enumerate() {
+            let file = fs::File::create(output_path)
+                .context("Failed to create output file")?;
+            let writer_sink = BufWriter::new(file);
+
+            let mut writer = Writer::new(writer_sink, FbxVersion::V7_4)
+                .map_err(|e| anyhow::anyhow!("Failed to create FBX writer: {:?}", e))?;
+
+            // Write FBX tree; split files share the scene, so only the first one reports its notes
+            let mut file_stats = ConversionStats::default();
+            write_fbx_tree(&mut writer, &gltf, &buffers, &images, &exported, clips, args, &mut file_stats)?;
+            if index == 0 {
+                // Scenes sharing meshes would repeat the same warnings
+                for warning in file_stats.warnings {
+                    if !stats.warnings.contains(&warning) {
+                        stats.warnings.push(warning);
+                    }
+                }
+                stats.clamped_vertices.extend(file_stats.clamped_vertices);
+            }
 
-    let file = fs::File::create(&output_path)
-        .context("Failed to create output file")?;
-    let writer_sink = BufWriter::new(file);
+            // Finalize FBX file
+            let footer = FbxFooter::default();
+            writer.finalize(&footer)
+                .map_err(|e| anyhow::anyhow!("Failed to finalize FBX: {:?}", e))?;
 
-    let mut writer = Writer::new(writer_sink, FbxVersion::V7_4)
-        .map_err(|e| anyhow::anyhow!("Failed to create FBX writer: {:?}", e))?;
+            output_paths.push(output_path.clone());
+        }
+        output_paths.extend(clip_list);
+    }
+
+    Ok((output_paths, stats))
+}
+
+// A file name part made valid on any OS and unique (case-insensitively) among `used_names`
+fn unique_file_name(name: &str, used_names: &mut Vec<String>) -> String {
+    let base_name: String = name.chars()
+        .map(|c| if c.is_control() || "<>:\"/\\|?*".contains(c) { '_' } else { c })
+        .collect();
+    let mut name = base_name.clone();
+    let mut suffix = 1;
+    while used_names.iter().any(|used| used.eq_ignore_ascii_case(&name)) {
+        name = format!("{}_{}", base_name, suffix);
+        suffix += 1;
+    }
+    used_names.push(name.clone());
+    name
+}
+
+// An encoded image file from the glTF, with a file name unique within the asset
+struct ImageFile {
+    file_name: String,
+    data: Vec<u8>,
+    // Where the file was written in external texture mode: (relative to the FBX, absolute)
+    external: Option<(String, String)>,
+}
+
+fn load_image_files(
+    gltf: &Document,
+    buffers: &[gltf::buffer::Data],
+    input_path: &Path,
+) -> Result<Vec<ImageFile>> {
+    let mut files: Vec<ImageFile> = Vec::new();
+
+    for image in gltf.images() {
+        let (data, mime_type, uri_stem) = match image.source() {
+            gltf::image::Source::View { view, mime_type } => {
+                let buffer = &buffers[view.buffer().index()];
+                let data = buffer[view.offset()..view.offset() + view.length()].to_vec();
+                (data, Some(mime_type), None)
+            }
+            gltf::image::Source::Uri { uri, mime_type } => {
+                if let Some(data_uri) = uri.strip_prefix("data:") {
+                    let (header, payload) = data_uri.split_once(',')
+                        .context("Malformed image data URI")?;
+                    let data = base64::decode(payload)
+                        .context("Invalid base64 image data")?;
+                    (data, mime_type.or_else(|| header.split(';').next()), None)
+                } else {
+                    let relative = urlencoding::decode(uri)
+                        .context("Invalid image URI")?
+                        .into_owned();
+                    let path = input_path.parent()
+                        .unwrap_or_else(|| Path::new("."))
+                        .join(&relative);
+                    let data = fs::read(&path)
+                        .with_context(|| format!("Failed to read image {}", path.display()))?;
+                    let stem = Path::new(&relative).file_stem()
+                        .and_then(|s| s.to_str())
+                        .map(str::to_string);
+                    (data, mime_type, stem)
+                }
+            }
+        };
+
+        let extension = image_extension(&data, mime_type);
+        let base_name = image.name()
+            .map(str::to_string)
+            .or(uri_stem)
+            .unwrap_or_else(|| format!("image{}", image.index()));
+        let base_name: String = base_name.chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+
+        // Several glTF images may share a name; file names must not (on any OS)
+        let mut file_name = format!("{}.{}", base_name, extension);
+        let mut suffix = 1;
+        while files.iter().any(|f| f.file_name.eq_ignore_ascii_case(&file_name)) {
+            file_name = format!("{}_{}.{}", base_name, suffix, extension);
+            suffix += 1;
+        }
+
+        files.push(ImageFile { file_name, data, external: None });
+    }
+
+    Ok(files)
+}
 
-    // Write FBX tree
-    write_fbx_tree(&mut writer, &gltf, &buffers)?;
+// Writes the images to the texture folder and records their paths for the FBX
+fn write_external_images(images: &mut [ImageFile], file_stem: &str, args: &Args) -> Result<()> {
+    let relative_dir = args.texture_dir.clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{}.fbm", file_stem)));
+    let dir = args.output.join(&relative_dir);
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create texture folder {}", dir.display()))?;
+
+    for image in images.iter_mut() {
+        // A shared --texture-dir may already hold another asset's file of the same name
+        let (stem, extension) = image.file_name.rsplit_once('.')
+            .map(|(stem, ext)| (stem.to_string(), ext.to_string()))
+            .unwrap_or_else(|| (image.file_name.clone(), String::new()));
+        let mut file_name = image.file_name.clone();
+        let mut suffix = 1;
+        while args.texture_dir.is_some() {
+            match fs::read(dir.join(&file_name)) {
+                Ok(existing) if existing != image.data => {
+                    file_name = format!("{}_{}.{}", stem, suffix, extension);
+                    suffix += 1;
+                }
+                _ => break,
+            }
+        }
+
+        let path = dir.join(&file_name);
+        fs::write(&path, &image.data)
+            .with_context(|| format!("Failed to write texture {}", path.display()))?;
+
+        let absolute = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        // RelativeFilename is resolved against the FBX's folder, i.e. the output folder
+        let relative = if relative_dir.is_absolute() {
+            relative_dir.strip_prefix(&args.output)
+                .map(|inside| inside.join(&file_name))
+                .unwrap_or_else(|_| absolute.clone())
+        } else {
+            relative_dir.join(&file_name)
+        };
+        image.external = Some((
+            relative.to_string_lossy().replace('\\', "/"),
+            absolute.to_string_lossy().into_owned(),
+        ));
+        image.file_name = file_name;
+    }
 
-    // Finalize FBX file
-    let footer = FbxFooter::default();
-    writer.finalize(&footer)
-        .map_err(|e| anyhow::anyhow!("Failed to finalize FBX: {:?}", e))?;
+    Ok(())
+}
 
-    Ok(output_path)
+// File extension from the image's magic bytes, falling back to its MIME type
+fn image_extension(data: &[u8], mime_type: Option<&str>) -> &'static str {
+    if data.starts_with(b"\x89PNG") {
+        "png"
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        "jpg"
+    } else {
+        match mime_type {
+            Some("image/jpeg") => "jpg",
+            Some("image/webp") => "webp",
+            _ => "png",
+        }
+    }
 }
 
+// `exported` flags the nodes (by index) of the scene being written; everything else is left out
+#[allow(clippy::too_many_arguments)]
 fn write_fbx_tree(
     writer: &mut Writer<BufWriter<fs::File>>,
     gltf: &Document,
     buffers: &[gltf::buffer::Data],
+    images: &[ImageFile],
+    exported: &[bool],
+    animations: &[AnimationClip],
+    args: &Args,
+    stats: &mut ConversionStats,
 ) -> Result<()> {
+    let mut ids = ObjectIds::default();
+
     // Write FBXHeaderExtension node
     {
         let mut attrs = writer.new_node("FBXHeaderExtension")
@@ -595,31 +1017,185 @@ fn write_fbx_tree(
             .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
     }
 
+    // Geometries used by the exported nodes, keyed by the node owning them
+    let owners = geometry_owners(gltf, args);
+    let geometries = exported_geometries(gltf, exported, &owners);
+
+    // EXT_mesh_gpu_instancing transforms of the exported nodes (by index)
+    let instances: Vec<Option<Vec<InstanceTransform>>> = gltf.nodes()
+        .map(|node| node_instances(gltf, &node, buffers).filter(|_| exported[node.index()]))
+        .collect();
+
+    // Materials referenced by triangle primitives; unused ones are not exported
+    let mut used_materials: Vec<gltf::Material> = Vec::new();
+    for (_, mesh, _) in &geometries {
+        for primitive in mesh.primitives().filter(|p| is_triangle_mode(p.mode())) {
+            let material = primitive.material();
+            if !used_materials.iter().any(|m| m.index() == material.index()) {
+                used_materials.push(material);
+            }
+        }
+    }
+
     // Write Objects node
     {
         writer.new_node("Objects")
             .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
 
         // Write geometries (meshes)
-        for mesh in gltf.meshes() {
-            let mesh_id = (mesh.index() + 1) * 10000;
+        for (owner, mesh, _) in &geometries {
+            let mesh_id = ids.id(ObjectKey::Geometry(*owner));
             let mesh_name = mesh.name().unwrap_or("Mesh").to_string();
             
-            let mut all_positions = Vec::new();
+            let mut all_positions: Vec<[f32; 3]> = Vec::new();
             let mut all_indices = Vec::new();
+            // One normal per polygon vertex (ByPolygonVertex / Direct)
+            let mut all_normals: Vec<[f32; 3]> = Vec::new();
+            // One list of per-vertex UVs per TEXCOORD set
+            let mut all_uvs: Vec<Vec<[f32; 2]>> = Vec::new();
+            // Per-vertex RGBA from COLOR_0, if any primitive has it
+            let mut all_colors: Vec<[f32; 4]> = Vec::new();
+            // One tangent (xyz + handedness) per polygon vertex, zero where unavailable
+            let mut all_tangents: Vec<[f32; 4]> = Vec::new();
+            // The UV set the first primitive with tangents has them for
+            let mut tangent_uv_set: Option<u32> = None;
+            // Per-triangle index into the materials connected to the Model
+            let mut polygon_materials: Vec<i32> = Vec::new();
+            let materials = mesh_materials(mesh);
             let mut vertex_offset = 0u32;
-            
-            for primitive in mesh.primitives() {
+
+            // --instancing collapse bakes one copy of the primitives per instance into the Geometry
+            let baked_instances: Vec<Option<&InstanceTransform>> = match &instances[*owner] {
+                Some(transforms) if args.instancing == InstancingMode::Collapse => transforms.iter().map(Some).collect(),
+                _ => vec![None],
+            };
+            let primitives = baked_instances.iter()
+                .enumerate()
+                .flat_map(|(copy, &instance)| mesh.primitives().map(move |primitive| (copy, instance, primitive)));
+
+            for (copy, instance, primitive) in primitives {
+                if !is_triangle_mode(primitive.mode()) {
+                    if copy == 0 {
+                        stats.warnings.push(format!(
+                            "Mesh '{}' primitive {} uses {:?} mode; only triangles are converted",
+                            mesh_name, primitive.index(), primitive.mode()
+                        ));
+                    }
+                    continue;
+                }
+                if let Some(problem) = primitive_problem(&primitive, buffers) {
+                    if copy == 0 {
+                        stats.warnings.push(format!(
+                            "Mesh '{}' primitive {} {}; it was skipped",
+                            mesh_name, primitive.index(), problem
+                        ));
+                    }
+                    continue;
+                }
+
                 let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-                
-                if let Some(iter) = reader.read_positions() {
-                    all_positions.extend(iter);
+
+                let positions: Vec<[f32; 3]> = match reader.read_positions() {
+                    Some(iter) => iter.map(|p| instance.map_or(p, |instance| instance_point(instance, p))).collect(),
+                    None => continue,
+                };
+
+                // Non-indexed primitives draw their vertices in order
+                let indices: Vec<u32> = match reader.read_indices() {
+                    Some(iter) => iter.into_u32().collect(),
+                    None => (0..positions.len() as u32).collect(),
+                };
+                let mut indices = triangle_list(primitive.mode(), indices);
+                // A mirroring instance turns the triangles inside out unless their winding flips too
+                if instance.is_some_and(instance_mirrors) {
+                    indices.chunks_exact_mut(3).for_each(|tri| tri.swap(1, 2));
                 }
-                
-                if let Some(iter) = reader.read_indices() {
-                    all_indices.extend(iter.into_u32().map(|i| i + vertex_offset));
+
+                let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|iter| {
+                    iter.map(|n| match instance {
+                        Some(instance) => normalize(instance_normal(instance, n)).unwrap_or(n),
+                        None => n,
+                    })
+                    .collect()
+                });
+
+                // TEXCOORD_n sets; vertices of primitives lacking a set get (0, 0)
+                let mut set = 0;
+                while let Some(iter) = reader.read_tex_coords(set) {
+                    if all_uvs.len() <= set as usize {
+                        all_uvs.push(vec![[0.0, 0.0]; all_positions.len()]);
+                    }
+                    all_uvs[set as usize].extend(iter.into_f32());
+                    set += 1;
                 }
-                
+                for uvs in all_uvs.iter_mut() {
+                    uvs.resize(all_positions.len() + positions.len(), [0.0, 0.0]);
+                }
+
+                // COLOR_0; vertices of primitives without colors stay white
+                if let Some(iter) = reader.read_colors(0) {
+                    all_colors.resize(all_positions.len(), [1.0; 4]);
+                    all_colors.extend(iter.into_rgba_f32().map(|c| match args.vertex_colors {
+                        ColorSpace::Linear => c,
+                        ColorSpace::Srgb => [
+                            linear_to_srgb(c[0]),
+                            linear_to_srgb(c[1]),
+                            linear_to_srgb(c[2]),
+                            c[3],
+                        ],
+                    }));
+                } else if !all_colors.is_empty() {
+                    all_colors.resize(all_positions.len() + positions.len(), [1.0; 4]);
+                }
+
+                let mut corner_normals: Vec<[f32; 3]> = Vec::with_capacity(indices.len());
+                for tri in indices.chunks_exact(3) {
+                    match &normals {
+                        Some(normals) => {
+                            corner_normals.extend(tri.iter().map(|&i| normals[i as usize]));
+                        }
+                        None => {
+                            // glTF spec: missing normals mean flat shading
+                            let normal = face_normal(
+                                positions[tri[0] as usize],
+                                positions[tri[1] as usize],
+                                positions[tri[2] as usize],
+                            );
+                            corner_normals.extend([normal; 3]);
+                        }
+                    }
+                    all_indices.extend(tri.iter().map(|&i| i + vertex_offset));
+                }
+
+                // TANGENT, or generated from the normals and the UV set the normal texture uses
+                let tangents: Option<Vec<[f32; 4]>> = reader.read_tangents()
+                    .map(|iter| iter.map(|t| instance.map_or(t, |instance| instance_tangent(instance, t))).collect());
+                let tangent_set = primitive.material().normal_texture().map_or(0, |info| info.tex_coord());
+                let corner_tangents = match (&tangents, reader.read_tex_coords(tangent_set)) {
+                    (Some(tangents), _) => {
+                        tangent_uv_set.get_or_insert(tangent_set);
+                        indices.chunks_exact(3)
+                            .flatten()
+                            .map(|&i| tangents[i as usize])
+                            .collect()
+                    }
+                    (None, Some(iter)) => {
+                        tangent_uv_set.get_or_insert(tangent_set);
+                        let uvs: Vec<[f32; 2]> = iter.into_f32().collect();
+                        generate_tangents(&positions, &uvs, &indices, &corner_normals)
+                    }
+                    (None, None) => vec![[0.0; 4]; corner_normals.len()],
+                };
+
+                let material_slot = materials.iter()
+                    .position(|&m| m == primitive.material().index())
+                    .unwrap_or(0) as i32;
+                polygon_materials.extend(std::iter::repeat_n(material_slot, corner_normals.len() / 3));
+
+                all_normals.extend(corner_normals);
+                all_tangents.extend(corner_tangents);
+
+                all_positions.extend(positions);
                 vertex_offset = all_positions.len() as u32;
             }
             
@@ -627,7 +1203,7 @@ fn write_fbx_tree(
             {
                 let mut attrs = writer.new_node("Geometry")
                     .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
-                attrs.append_i64(mesh_id as i64)
+                attrs.append_i64(mesh_id)
                     .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
                 attrs.append_string_direct(&format!("Geometry::{}", mesh_name))
                     .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
@@ -666,64 +1242,3177 @@ fn write_fbx_tree(
                     writer.close_node()
                         .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
                 }
-                
+
+                // Write LayerElementNormal child node
+                {
+                    let mut attrs = writer.new_node("LayerElementNormal")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_i32(0)
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    drop(attrs);
+
+                    write_i32_node(writer, "Version", 101)?;
+                    write_string_node(writer, "Name", "")?;
+                    write_string_node(writer, "MappingInformationType", "ByPolygonVertex")?;
+                    write_string_node(writer, "ReferenceInformationType", "Direct")?;
+                    write_f64_array_node(
+                        writer,
+                        "Normals",
+                        all_normals.iter().flat_map(|n| [n[0] as f64, n[1] as f64, n[2] as f64]),
+                    )?;
+
+                    writer.close_node()
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End LayerElementNormal
+                }
+
+                // Write LayerElementTangent and LayerElementBinormal child nodes
+                let has_tangents = all_tangents.iter().any(|t| t[3] != 0.0);
+                if has_tangents {
+                    // glTF: bitangent = cross(normal, tangent.xyz) * tangent.w
+                    let binormals: Vec<[f32; 3]> = all_normals.iter().zip(&all_tangents)
+                        .map(|(n, t)| {
+                            let b = cross(*n, [t[0], t[1], t[2]]);
+                            [b[0] * t[3], b[1] * t[3], b[2] * t[3]]
+                        })
+                        .collect();
+
+                    for (element, name, values) in [
+                        ("LayerElementTangent", "Tangents", all_tangents.iter().map(|t| [t[0], t[1], t[2]]).collect::<Vec<_>>()),
+                        ("LayerElementBinormal", "Binormals", binormals),
+                    ] {
+                        let mut attrs = writer.new_node(element)
+                            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                        attrs.append_i32(0)
+                            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                        drop(attrs);
+
+                        write_i32_node(writer, "Version", 102)?;
+                        write_string_node(writer, "Name", &uv_set_name(tangent_uv_set.unwrap_or(0) as usize))?;
+                        write_string_node(writer, "MappingInformationType", "ByPolygonVertex")?;
+                        write_string_node(writer, "ReferenceInformationType", "Direct")?;
+                        write_f64_array_node(
+                            writer,
+                            name,
+                            values.iter().flat_map(|v| [v[0] as f64, v[1] as f64, v[2] as f64]),
+                        )?;
+                        write_f64_array_node(
+                            writer,
+                            &format!("{}W", name),
+                            all_tangents.iter().map(|t| t[3] as f64),
+                        )?;
+
+                        writer.close_node()
+                            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End LayerElementTangent / LayerElementBinormal
+                    }
+                }
+
+                // Write LayerElementUV child nodes
+                for (set, uvs) in all_uvs.iter().enumerate() {
+                    let mut attrs = writer.new_node("LayerElementUV")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_i32(set as i32)
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    drop(attrs);
+
+                    write_i32_node(writer, "Version", 101)?;
+                    write_string_node(writer, "Name", &uv_set_name(set))?;
+                    write_string_node(writer, "MappingInformationType", "ByPolygonVertex")?;
+                    write_string_node(writer, "ReferenceInformationType", "IndexToDirect")?;
+                    // FBX puts the V origin at the bottom, glTF at the top
+                    write_f64_array_node(
+                        writer,
+                        "UV",
+                        uvs.iter().flat_map(|uv| [uv[0] as f64, 1.0 - uv[1] as f64]),
+                    )?;
+                    write_i32_array_node(writer, "UVIndex", all_indices.iter().map(|&i| i as i32))?;
+
+                    writer.close_node()
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End LayerElementUV
+                }
+
+                // Write LayerElementColor child node
+                if !all_colors.is_empty() {
+                    let mut attrs = writer.new_node("LayerElementColor")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_i32(0)
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    drop(attrs);
+
+                    write_i32_node(writer, "Version", 101)?;
+                    write_string_node(writer, "Name", "Col")?;
+                    write_string_node(writer, "MappingInformationType", "ByPolygonVertex")?;
+                    write_string_node(writer, "ReferenceInformationType", "IndexToDirect")?;
+                    write_f64_array_node(
+                        writer,
+                        "Colors",
+                        all_colors.iter().flat_map(|c| c.map(|v| v as f64)),
+                    )?;
+                    write_i32_array_node(writer, "ColorIndex", all_indices.iter().map(|&i| i as i32))?;
+
+                    writer.close_node()
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End LayerElementColor
+                }
+
+                // Write LayerElementMaterial child node
+                {
+                    let mut attrs = writer.new_node("LayerElementMaterial")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_i32(0)
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    drop(attrs);
+
+                    write_i32_node(writer, "Version", 101)?;
+                    write_string_node(writer, "Name", "")?;
+                    write_string_node(writer, "MappingInformationType", "ByPolygon")?;
+                    write_string_node(writer, "ReferenceInformationType", "IndexToDirect")?;
+                    write_i32_array_node(writer, "Materials", polygon_materials.iter().copied())?;
+
+                    writer.close_node()
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End LayerElementMaterial
+                }
+
+                // Write Layer child nodes; extra UV sets go on their own layers
+                let mut base_layer = vec![("LayerElementNormal", 0), ("LayerElementMaterial", 0)];
+                if has_tangents {
+                    base_layer.push(("LayerElementTangent", 0));
+                    base_layer.push(("LayerElementBinormal", 0));
+                }
+                if !all_uvs.is_empty() {
+                    base_layer.push(("LayerElementUV", 0));
+                }
+                if !all_colors.is_empty() {
+                    base_layer.push(("LayerElementColor", 0));
+                }
+                write_layer(writer, 0, &base_layer)?;
+                for set in 1..all_uvs.len() {
+                    write_layer(writer, set as i32, &[("LayerElementUV", set as i32)])?;
+                }
+
                 writer.close_node()
                     .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Geometry
             }
         }
         
-        // Write models (nodes)
-        for node in gltf.nodes() {
-            let node_id = (node.index() + 1) * 20000;
-            let node_name = node.name().unwrap_or("Node").to_string();
-            
+        // Write materials, including glTF's default material if a primitive relies on it
+        for material in &used_materials {
+            let material_name = material.name().unwrap_or(if material.index().is_some() { "Material" } else { "Default" });
+            let pbr = material.pbr_metallic_roughness();
+            let base_color = pbr.base_color_factor();
+            let metallic = pbr.metallic_factor() as f64;
+            let roughness = pbr.roughness_factor() as f64;
+            let emissive = material.emissive_factor();
+            // Alpha only matters for blended materials; OPAQUE and MASK ignore it in glTF
+            let alpha = match material.alpha_mode() {
+                gltf::material::AlphaMode::Blend => base_color[3] as f64,
+                _ => 1.0,
+            };
+            let diffuse = [base_color[0] as f64, base_color[1] as f64, base_color[2] as f64];
+            // Metals tint their reflections; dielectrics reflect ~4% white
+            let specular = diffuse.map(|c| 0.04 + (c - 0.04) * metallic);
+            // Blinn-Phong exponent approximating the GGX roughness
+            let alpha_roughness = (roughness * roughness).max(1e-3);
+            let shininess = (2.0 / (alpha_roughness * alpha_roughness) - 2.0).clamp(2.0, 1024.0);
+
+            let mut attrs = writer.new_node("Material")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_i64(ids.id(ObjectKey::Material(material.index())))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct(&format!("Material::{}", material_name))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct("")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            drop(attrs);
+
+            write_i32_node(writer, "Version", 102)?;
+            write_string_node(writer, "ShadingModel", "phong")?;
+            write_i32_node(writer, "MultiLayer", 0)?;
+
+            // Properties70
             {
-                let mut attrs = writer.new_node("Model")
-                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
-                attrs.append_i64(node_id as i64)
-                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
-                attrs.append_string_direct(&format!("Model::{}", node_name))
+                writer.new_node("Properties70")
                     .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
-                attrs.append_string_direct("Mesh")
-                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
-                drop(attrs);
-                
+                write_property_string(writer, "ShadingModel", "KString", "", "", "Phong")?;
+                write_property_f64(writer, "DiffuseColor", "Color", "", "A", &diffuse)?;
+                write_property_f64(writer, "DiffuseFactor", "Number", "", "A", &[1.0])?;
+                write_property_f64(writer, "EmissiveColor", "Color", "", "A", &emissive.map(|c| c as f64))?;
+                write_property_f64(writer, "EmissiveFactor", "Number", "", "A", &[1.0])?;
+                write_property_f64(writer, "SpecularColor", "Color", "", "A", &specular)?;
+                write_property_f64(writer, "SpecularFactor", "Number", "", "A", &[1.0 - roughness])?;
+                write_property_f64(writer, "ShininessExponent", "Number", "", "A", &[shininess])?;
+                write_property_f64(writer, "TransparentColor", "Color", "", "A", &[1.0, 1.0, 1.0])?;
+                write_property_f64(writer, "TransparencyFactor", "Number", "", "A", &[1.0 - alpha])?;
+                write_property_f64(writer, "Opacity", "double", "Number", "", &[alpha])?;
                 writer.close_node()
-                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Model
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // Close Properties70
             }
+
+            writer.close_node()
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Material
         }
-        
-        writer.close_node()
-            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Objects
-    }
 
-    // Write Connections node
-    {
-        writer.new_node("Connections")
-            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
-            
-        for node in gltf.nodes() {
-            if let Some(mesh) = node.mesh() {
-                let node_id = (node.index() + 1) * 20000;
-                let mesh_id = (mesh.index() + 1) * 10000;
-                
-                let mut attrs = writer.new_node("C")
+        // Write textures used by the materials, each backed by a Video holding the image file
+        let mut used_textures: Vec<(gltf::Texture, u32)> = Vec::new();
+        for material in &used_materials {
+            for (texture, tex_coord, _) in material_textures(material) {
+                if !used_textures.iter().any(|(t, _)| t.index() == texture.index()) {
+                    used_textures.push((texture, tex_coord));
+                }
+            }
+        }
+
+        let mut written_videos: Vec<usize> = Vec::new();
+        for (texture, tex_coord) in &used_textures {
+            let image_index = texture.source().index();
+            let image = &images[image_index];
+            let video_name = format!("Video::{}", image.file_name);
+            // External files are referenced by path; embedded ones by their bare name
+            let (relative_file_name, file_name) = match &image.external {
+                Some((relative, absolute)) => (relative.as_str(), absolute.as_str()),
+                None => (image.file_name.as_str(), image.file_name.as_str()),
+            };
+
+            if !written_videos.contains(&image_index) {
+                written_videos.push(image_index);
+
+                let mut attrs = writer.new_node("Video")
                     .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
-                attrs.append_string_direct("OO")
+                attrs.append_i64(ids.id(ObjectKey::Video(image_index)))
                     .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
-                attrs.append_i64(mesh_id as i64)
+                attrs.append_string_direct(&video_name)
                     .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
-                attrs.append_i64(node_id as i64)
+                attrs.append_string_direct("Clip")
                     .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
                 drop(attrs);
+
+                write_string_node(writer, "Type", "Clip")?;
+
+                // Properties70
+                {
+                    writer.new_node("Properties70")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    write_property_string(writer, "Path", "KString", "XRefUrl", "", file_name)?;
+                    writer.close_node()
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // Close Properties70
+                }
+
+                write_i32_node(writer, "UseMipMap", 0)?;
+                write_string_node(writer, "Filename", file_name)?;
+                write_string_node(writer, "RelativeFilename", relative_file_name)?;
+
+                // Content: the embedded image file
+                if image.external.is_none() {
+                    let mut attrs = writer.new_node("Content")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_binary_direct(&image.data)
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    drop(attrs);
+                    writer.close_node()
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                }
+
                 writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Video
+            }
+
+            let texture_name = format!(
+                "Texture::{}",
+                texture.name().unwrap_or(&image.file_name)
+            );
+            let sampler = texture.sampler();
+
+            let mut attrs = writer.new_node("Texture")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_i64(ids.id(ObjectKey::Texture(texture.index())))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct(&texture_name)
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct("")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            drop(attrs);
+
+            write_string_node(writer, "Type", "TextureVideoClip")?;
+            write_i32_node(writer, "Version", 202)?;
+            write_string_node(writer, "TextureName", &texture_name)?;
+
+            // Properties70
+            {
+                writer.new_node("Properties70")
                     .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                write_property_string(writer, "UVSet", "KString", "", "", &uv_set_name(*tex_coord as usize))?;
+                write_property_i32(writer, "UseMaterial", "bool", "", "", 1)?;
+                // FBX wrap modes: 0 = repeat, 1 = clamp
+                for (name, mode) in [("WrapModeU", sampler.wrap_s()), ("WrapModeV", sampler.wrap_t())] {
+                    let clamp = mode == gltf::texture::WrappingMode::ClampToEdge;
+                    write_property_i32(writer, name, "enum", "", "", clamp as i32)?;
+                }
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // Close Properties70
             }
+
+            write_string_node(writer, "Media", &video_name)?;
+            write_string_node(writer, "FileName", file_name)?;
+            write_string_node(writer, "RelativeFilename", relative_file_name)?;
+            write_f64_values_node(writer, "ModelUVTranslation", &[0.0, 0.0])?;
+            write_f64_values_node(writer, "ModelUVScaling", &[1.0, 1.0])?;
+            write_string_node(writer, "Texture_Alpha_Source", "None")?;
+
+            writer.close_node()
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Texture
         }
-        
-        writer.close_node()
-            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Connections
-    }
 
-    Ok(())
+        // Write skeleton attributes for skin joints; joints without a joint parent are roots
+        let parents = node_parents(gltf);
+        let is_joint = joint_nodes(gltf);
+        for node in gltf.nodes().filter(|n| exported[n.index()] && is_joint[n.index()]) {
+            let node_name = node.name().unwrap_or("Node").to_string();
+            let is_root = parents[node.index()].is_none_or(|parent| !is_joint[parent]);
+            let skeleton_type = if is_root { "Root" } else { "LimbNode" };
+            let bone_length = bone_length(&node, &is_joint, &parents, gltf);
+
+            let mut attrs = writer.new_node("NodeAttribute")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_i64(ids.id(ObjectKey::NodeAttribute(node.index())))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct(&format!("NodeAttribute::{}", node_name))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct(skeleton_type)
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            drop(attrs);
+
+            // Properties70
+            {
+                writer.new_node("Properties70")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                write_property_f64(writer, "Size", "double", "Number", "", &[bone_length])?;
+                write_property_f64(writer, "LimbLength", "double", "Number", "H", &[bone_length])?;
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // Close Properties70
+            }
+
+            // TypeFlags: "Skeleton" for limbs, "Null", "Skeleton", "Root" for roots
+            {
+                let mut attrs = writer.new_node("TypeFlags")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                let flags: &[&str] = if is_root { &["Null", "Skeleton", "Root"] } else { &["Skeleton"] };
+                for flag in flags {
+                    attrs.append_string_direct(flag)
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                }
+                drop(attrs);
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            }
+
+            writer.close_node()
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End NodeAttribute
+        }
+
+        // Write camera attributes
+        for node in gltf.nodes().filter(|n| exported[n.index()] && !is_joint[n.index()]) {
+            let Some(camera) = node.camera() else {
+                continue;
+            };
+            let camera_name = camera.name().or(node.name()).unwrap_or("Camera").to_string();
+
+            // FBX also describes the lens by its film back (in inches); use a full-frame 24mm height
+            const FILM_HEIGHT_INCHES: f64 = 24.0 / 25.4;
+            let (projection_type, aspect_ratio, vertical_fov, near, far, ortho_zoom) = match camera.projection() {
+                gltf::camera::Projection::Perspective(perspective) => (
+                    0,
+                    // Without an aspect ratio glTF uses the viewport's; assume 16:9
+                    perspective.aspect_ratio().unwrap_or(16.0 / 9.0) as f64,
+                    perspective.yfov() as f64,
+                    perspective.znear() as f64,
+                    perspective.zfar().unwrap_or(10_000.0) as f64,
+                    1.0,
+                ),
+                gltf::camera::Projection::Orthographic(orthographic) => (
+                    1,
+                    (orthographic.xmag() / orthographic.ymag()).abs() as f64,
+                    std::f64::consts::FRAC_PI_4,
+                    orthographic.znear() as f64,
+                    orthographic.zfar() as f64,
+                    // OrthoZoom is the full extent of the larger side, as Blender reads it
+                    2.0 * orthographic.xmag().abs().max(orthographic.ymag().abs()) as f64,
+                ),
+            };
+            let horizontal_fov = 2.0 * ((vertical_fov / 2.0).tan() * aspect_ratio).atan();
+            let focal_length = (FILM_HEIGHT_INCHES * 25.4 / 2.0) / (vertical_fov / 2.0).tan();
+
+            let mut attrs = writer.new_node("NodeAttribute")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_i64(ids.id(ObjectKey::NodeAttribute(node.index())))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct(&format!("NodeAttribute::{}", camera_name))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct("Camera")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            drop(attrs);
+
+            // Properties70
+            {
+                writer.new_node("Properties70")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                write_property_i32(writer, "CameraProjectionType", "enum", "", "", projection_type)?;
+                write_property_f64(writer, "AspectWidth", "double", "Number", "", &[1080.0 * aspect_ratio])?;
+                write_property_f64(writer, "AspectHeight", "double", "Number", "", &[1080.0])?;
+                write_property_f64(writer, "FilmWidth", "double", "Number", "", &[FILM_HEIGHT_INCHES * aspect_ratio])?;
+                write_property_f64(writer, "FilmHeight", "double", "Number", "", &[FILM_HEIGHT_INCHES])?;
+                write_property_f64(writer, "FilmAspectRatio", "double", "Number", "", &[aspect_ratio])?;
+                // ApertureMode 2: FieldOfView is the vertical angle
+                write_property_i32(writer, "ApertureMode", "enum", "", "", 2)?;
+                write_property_f64(writer, "FieldOfView", "FieldOfView", "", "A", &[vertical_fov.to_degrees()])?;
+                write_property_f64(writer, "FieldOfViewX", "FieldOfViewX", "", "A", &[horizontal_fov.to_degrees()])?;
+                write_property_f64(writer, "FieldOfViewY", "FieldOfViewY", "", "A", &[vertical_fov.to_degrees()])?;
+                write_property_f64(writer, "FocalLength", "Number", "", "A", &[focal_length])?;
+                write_property_f64(writer, "NearPlane", "double", "Number", "", &[near])?;
+                write_property_f64(writer, "FarPlane", "double", "Number", "", &[far])?;
+                write_property_f64(writer, "OrthoZoom", "double", "Number", "", &[ortho_zoom])?;
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // Close Properties70
+            }
+
+            write_string_node(writer, "TypeFlags", "Camera")?;
+            write_i32_node(writer, "GeometryVersion", 124)?;
+            // Legacy camera frame: FBX cameras look down their local +X
+            write_f64_values_node(writer, "Position", &[0.0, 0.0, 0.0])?;
+            write_f64_values_node(writer, "Up", &[0.0, 1.0, 0.0])?;
+            write_f64_values_node(writer, "LookAt", &[1.0, 0.0, 0.0])?;
+            write_i32_node(writer, "ShowInfoOnMoving", 1)?;
+            write_i32_node(writer, "ShowAudio", 0)?;
+            write_f64_values_node(writer, "AudioColor", &[0.0, 1.0, 0.0])?;
+            write_f64_values_node(writer, "CameraOrthoZoom", &[1.0])?;
+
+            writer.close_node()
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End NodeAttribute
+        }
+
+        // Write light attributes; a node that is also a joint or camera keeps that attribute
+        for node in gltf.nodes().filter(|n| exported[n.index()] && !is_joint[n.index()] && n.camera().is_none()) {
+            let Some(light) = node.light() else {
+                continue;
+            };
+            let light_name = light.name().or(node.name()).unwrap_or("Light").to_string();
+
+            // LightType 0 point, 1 directional, 2 spot; FBX cone angles are full angles in degrees
+            let (light_type, cone_angles) = match light.kind() {
+                gltf::khr_lights_punctual::Kind::Point => (0, None),
+                gltf::khr_lights_punctual::Kind::Directional => (1, None),
+                gltf::khr_lights_punctual::Kind::Spot { inner_cone_angle, outer_cone_angle } => (
+                    2,
+                    Some(((2.0 * inner_cone_angle as f64).to_degrees(), (2.0 * outer_cone_angle as f64).to_degrees())),
+                ),
+            };
+            // Punctual lights fall off with the inverse square of the distance; the sun does not
+            let decay_type = if light_type == 1 { 0 } else { 2 };
+
+            let mut attrs = writer.new_node("NodeAttribute")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_i64(ids.id(ObjectKey::NodeAttribute(node.index())))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct(&format!("NodeAttribute::{}", light_name))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct("Light")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            drop(attrs);
+
+            // Properties70
+            {
+                writer.new_node("Properties70")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                write_property_i32(writer, "LightType", "enum", "", "", light_type)?;
+                write_property_i32(writer, "CastLight", "bool", "", "", 1)?;
+                write_property_f64(writer, "Color", "Color", "", "A", &light.color().map(|c| c as f64))?;
+                write_property_f64(writer, "Intensity", "Number", "", "A", &[light.intensity() as f64 * args.light_intensity_scale])?;
+                write_property_i32(writer, "DecayType", "enum", "", "", decay_type)?;
+                if let Some((inner_angle, outer_angle)) = cone_angles {
+                    write_property_f64(writer, "InnerAngle", "Number", "", "A", &[inner_angle])?;
+                    write_property_f64(writer, "OuterAngle", "Number", "", "A", &[outer_angle])?;
+                }
+                if let Some(range) = light.range() {
+                    write_property_i32(writer, "EnableFarAttenuation", "bool", "", "", 1)?;
+                    write_property_f64(writer, "FarAttenuationEnd", "Number", "", "A", &[range as f64])?;
+                }
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // Close Properties70
+            }
+
+            write_string_node(writer, "TypeFlags", "Light")?;
+            write_i32_node(writer, "GeometryVersion", 124)?;
+
+            writer.close_node()
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End NodeAttribute
+        }
+
+        // Write models (nodes)
+        for node in gltf.nodes().filter(|n| exported[n.index()]) {
+            let node_id = ids.id(ObjectKey::Model(node.index()));
+            let node_name = node.name().unwrap_or("Node").to_string();
+            // A camera or light moved to a child Model leaves this one a plain Mesh
+            let view = view_attribute(&node, &is_joint).filter(|_| !has_attribute_model(&node, &is_joint));
+            let model_type = match view {
+                _ if is_joint[node.index()] => "LimbNode",
+                Some((model_type, _)) => model_type,
+                None => "Mesh",
+            };
+            let post_rotation = view.map(|(_, post_rotation)| post_rotation);
+
+            {
+                let mut attrs = writer.new_node("Model")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_i64(node_id)
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct(&format!("Model::{}", node_name))
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct(model_type)
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                drop(attrs);
+
+                let transform = node.transform();
+                let (translation, rotation, scale) = transform.clone().decomposed();
+
+                // TRS cannot express shear; the closest TRS is written instead
+                if let gltf::scene::Transform::Matrix { matrix } = transform {
+                    let recomposed = gltf::scene::Transform::Decomposed { translation, rotation, scale }.matrix();
+                    let sheared = matrix.iter().flatten()
+                        .zip(recomposed.iter().flatten())
+                        .any(|(a, b)| (a - b).abs() > 1e-4);
+                    if sheared {
+                        stats.warnings.push(format!("Node '{}' has a sheared matrix; shear was dropped", node_name));
+                    }
+                }
+
+                if node.mesh().is_some()
+                    && node.extension_value(EXT_MESH_GPU_INSTANCING).is_some()
+                    && instances[node.index()].is_none()
+                {
+                    stats.warnings.push(if node.skin().is_some() {
+                        format!("Node '{}' instances a skinned mesh; only the node itself was converted", node_name)
+                    } else {
+                        format!("Node '{}' has unreadable EXT_mesh_gpu_instancing attributes; instances were skipped", node_name)
+                    });
+                }
+
+                // Properties70
+                {
+                    writer.new_node("Properties70")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    write_property_f64(writer, "Lcl Translation", "Lcl Translation", "", "A", &translation.map(|v| v as f64))?;
+                    write_property_i32(writer, "RotationActive", "bool", "", "", 1)?;
+                    write_property_i32(writer, "RotationOrder", "enum", "", "", args.rotation_order.fbx_value())?;
+                    write_property_f64(writer, "Lcl Rotation", "Lcl Rotation", "", "A", &quat_to_euler(rotation, args.rotation_order))?;
+                    if let Some(post_rotation) = post_rotation {
+                        write_property_f64(writer, "PostRotation", "Vector3D", "Vector", "", &post_rotation)?;
+                    }
+                    write_property_f64(writer, "Lcl Scaling", "Lcl Scaling", "", "A", &scale.map(|v| v as f64))?;
+                    writer.close_node()
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // Close Properties70
+                }
+
+                // glTF double-sidedness lives on materials, FBX culling on the Model
+                if let Some(mesh) = node.mesh() {
+                    let double_sided = mesh.primitives().any(|p| p.material().double_sided());
+                    write_string_node(writer, "Culling", if double_sided { "CullingOff" } else { "CullingOnCCW" })?;
+                }
+
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Model
+            }
+        }
+        
+        // Write the child Models carrying the cameras and lights of nodes with a mesh or children
+        for node in gltf.nodes().filter(|n| exported[n.index()] && has_attribute_model(n, &is_joint)) {
+            let Some((model_type, post_rotation)) = view_attribute(&node, &is_joint) else {
+                continue;
+            };
+            let node_name = node.name().unwrap_or("Node").to_string();
+
+            let mut attrs = writer.new_node("Model")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_i64(ids.id(ObjectKey::AttributeModel(node.index())))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct(&format!("Model::{}_{}", node_name, model_type))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct(model_type)
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            drop(attrs);
+
+            // Properties70
+            {
+                writer.new_node("Properties70")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                write_property_i32(writer, "RotationActive", "bool", "", "", 1)?;
+                write_property_f64(writer, "PostRotation", "Vector3D", "Vector", "", &post_rotation)?;
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // Close Properties70
+            }
+
+            writer.close_node()
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Model
+        }
+
+        // Write EXT_mesh_gpu_instancing instances as child Models sharing their node's Geometry
+        for node in gltf.nodes().filter(|_| args.instancing == InstancingMode::Expand) {
+            let Some(transforms) = &instances[node.index()] else {
+                continue;
+            };
+            let node_name = node.name().unwrap_or("Node").to_string();
+            let double_sided = node.mesh().is_some_and(|mesh| mesh.primitives().any(|p| p.material().double_sided()));
+
+            for (instance, (translation, rotation, scale)) in transforms.iter().enumerate() {
+                let mut attrs = writer.new_node("Model")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_i64(ids.id(ObjectKey::InstanceModel(node.index(), instance)))
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct(&format!("Model::{}_{}", node_name, instance))
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct("Mesh")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                drop(attrs);
+
+                // Properties70
+                {
+                    writer.new_node("Properties70")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    write_property_f64(writer, "Lcl Translation", "Lcl Translation", "", "A", &translation.map(|v| v as f64))?;
+                    write_property_i32(writer, "RotationActive", "bool", "", "", 1)?;
+                    write_property_i32(writer, "RotationOrder", "enum", "", "", args.rotation_order.fbx_value())?;
+                    write_property_f64(writer, "Lcl Rotation", "Lcl Rotation", "", "A", &quat_to_euler(*rotation, args.rotation_order))?;
+                    write_property_f64(writer, "Lcl Scaling", "Lcl Scaling", "", "A", &scale.map(|v| v as f64))?;
+                    writer.close_node()
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // Close Properties70
+                }
+
+                write_string_node(writer, "Culling", if double_sided { "CullingOff" } else { "CullingOnCCW" })?;
+
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Model
+            }
+        }
+
+        // Write skins: one Skin deformer per skinned Geometry, one Cluster per joint
+        for (owner, mesh, skin) in &geometries {
+            let Some(skin) = skin else {
+                continue;
+            };
+            let skin_name = skin.name().unwrap_or("Skin").to_string();
+            let (influences, clamped_vertices) = mesh_vertex_influences(mesh, buffers, args.max_influences);
+            stats.clamped_vertices.insert(*owner, clamped_vertices);
+            let inverse_bind_matrices = skin_inverse_bind_matrices(skin, buffers);
+
+            let mut attrs = writer.new_node("Deformer")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_i64(ids.id(ObjectKey::SkinDeformer(*owner)))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct(&format!("Deformer::{}", skin_name))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct("Skin")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            drop(attrs);
+
+            write_i32_node(writer, "Version", 101)?;
+            write_f64_values_node(writer, "Link_DeformAcuracy", &[50.0])?;
+
+            writer.close_node()
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Deformer
+
+            for (slot, joint) in skin.joints().enumerate() {
+                let joint_name = joint.name().unwrap_or("Joint").to_string();
+                let (indexes, weights): (Vec<i32>, Vec<f64>) = influences.iter().enumerate()
+                    .flat_map(|(vertex, vertex_influences)| {
+                        vertex_influences.iter()
+                            .filter(move |&&(j, _)| j == slot)
+                            .map(move |&(_, w)| (vertex as i32, w as f64))
+                    })
+                    .unzip();
+
+                // Skinned mesh vertices are in the bind space glTF's inverse bind
+                // matrices map from, so the mesh binds at identity
+                let inverse_bind = inverse_bind_matrices[slot];
+                let bind = mat4_invert(&inverse_bind).unwrap_or(MAT4_IDENTITY);
+
+                let mut attrs = writer.new_node("Deformer")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_i64(ids.id(ObjectKey::Cluster(*owner, slot)))
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct(&format!("SubDeformer::{}", joint_name))
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct("Cluster")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                drop(attrs);
+
+                write_i32_node(writer, "Version", 100)?;
+                // UserData: "", ""
+                {
+                    let mut attrs = writer.new_node("UserData")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_string_direct("")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_string_direct("")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    drop(attrs);
+                    writer.close_node()
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                }
+                write_i32_array_node(writer, "Indexes", indexes)?;
+                write_f64_array_node(writer, "Weights", weights)?;
+                write_f64_array_node(writer, "Transform", inverse_bind)?;
+                write_f64_array_node(writer, "TransformLink", bind)?;
+
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Deformer
+            }
+        }
+
+        // Write morph targets: one BlendShape per Geometry, one channel and Shape geometry per target
+        for (owner, mesh, _) in &geometries {
+            let baked_instances: &[InstanceTransform] = match &instances[*owner] {
+                Some(transforms) if args.instancing == InstancingMode::Collapse => transforms,
+                _ => &[],
+            };
+            let shapes = mesh_morph_targets(mesh, buffers, baked_instances);
+            if shapes.is_empty() {
+                continue;
+            }
+            let names = morph_target_names(mesh, shapes.len());
+            let default_weights = gltf.nodes().nth(*owner)
+                .map_or(mesh.weights().unwrap_or(&[]), |node| morph_default_weights(&node, mesh));
+
+            {
+                let mut attrs = writer.new_node("Deformer")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_i64(ids.id(ObjectKey::BlendShape(*owner)))
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct(&format!("Deformer::{}", mesh.name().unwrap_or("Mesh")))
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct("BlendShape")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                drop(attrs);
+                write_i32_node(writer, "Version", 100)?;
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Deformer
+            }
+
+            for (target, (shape, name)) in shapes.iter().zip(&names).enumerate() {
+                // glTF weights are 0..1, DeformPercent is 0..100
+                let deform_percent = default_weights.get(target).copied().unwrap_or(0.0) as f64 * 100.0;
+
+                {
+                    let mut attrs = writer.new_node("Deformer")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_i64(ids.id(ObjectKey::BlendShapeChannel(*owner, target)))
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_string_direct(&format!("SubDeformer::{}", name))
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_string_direct("BlendShapeChannel")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    drop(attrs);
+
+                    write_i32_node(writer, "Version", 100)?;
+                    write_f64_values_node(writer, "DeformPercent", &[deform_percent])?;
+                    write_f64_array_node(writer, "FullWeights", [100.0])?;
+
+                    writer.new_node("Properties70")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    write_property_f64(writer, "DeformPercent", "Number", "", "A", &[deform_percent])?;
+                    writer.close_node()
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // Close Properties70
+
+                    writer.close_node()
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Deformer
+                }
+
+                {
+                    let mut attrs = writer.new_node("Geometry")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_i64(ids.id(ObjectKey::ShapeGeometry(*owner, target)))
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_string_direct(&format!("Geometry::{}", name))
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_string_direct("Shape")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    drop(attrs);
+
+                    write_i32_node(writer, "Version", 100)?;
+                    write_i32_array_node(writer, "Indexes", shape.indexes.iter().copied())?;
+                    write_f64_array_node(writer, "Vertices", shape.position_deltas.iter().flatten().map(|&v| v as f64))?;
+                    write_f64_array_node(writer, "Normals", shape.normal_deltas.iter().flatten().map(|&v| v as f64))?;
+
+                    writer.close_node()
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Geometry
+                }
+            }
+        }
+
+        // Write the bind pose: every joint at its bind matrix, skinned meshes at identity
+        let mut geometry_users: std::collections::HashMap<usize, Vec<usize>> = std::collections::HashMap::new();
+        for node in gltf.nodes().filter(|n| exported[n.index()]) {
+            if let Some(owner) = owners[node.index()] {
+                geometry_users.entry(owner).or_default().push(node.index());
+            }
+        }
+        let mut pose_nodes: Vec<(i64, [f64; 16])> = Vec::new();
+        let mut posed: std::collections::HashSet<i64> = std::collections::HashSet::new();
+        for (owner, _, skin) in &geometries {
+            let Some(skin) = skin else {
+                continue;
+            };
+            for &node in geometry_users.get(owner).into_iter().flatten() {
+                let id = ids.id(ObjectKey::Model(node));
+                if posed.insert(id) {
+                    pose_nodes.push((id, MAT4_IDENTITY));
+                }
+            }
+
+            let inverse_bind_matrices = skin_inverse_bind_matrices(skin, buffers);
+            for (slot, joint) in skin.joints().enumerate() {
+                // A joint shared by several skins keeps the first skin's bind matrix
+                let id = ids.id(ObjectKey::Model(joint.index()));
+                if !posed.insert(id) {
+                    continue;
+                }
+                let bind = mat4_invert(&inverse_bind_matrices[slot]).unwrap_or(MAT4_IDENTITY);
+                pose_nodes.push((id, bind));
+            }
+        }
+
+        if !pose_nodes.is_empty() {
+            let mut attrs = writer.new_node("Pose")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_i64(ids.id(ObjectKey::BindPose))
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct("Pose::BIND_POSES")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            attrs.append_string_direct("BindPose")
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            drop(attrs);
+
+            write_string_node(writer, "Type", "BindPose")?;
+            write_i32_node(writer, "Version", 100)?;
+            write_i32_node(writer, "NbPoseNodes", pose_nodes.len() as i32)?;
+
+            for (id, matrix) in &pose_nodes {
+                writer.new_node("PoseNode")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+
+                let mut attrs = writer.new_node("Node")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_i64(*id)
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                drop(attrs);
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+
+                write_f64_array_node(writer, "Matrix", *matrix)?;
+
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End PoseNode
+            }
+
+            writer.close_node()
+                .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Pose
+        }
+
+        // Write animations: one AnimationStack with a single AnimationLayer per glTF animation
+        for (animation_index, clip) in animations.iter().enumerate() {
+            {
+                let mut attrs = writer.new_node("AnimationStack")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_i64(ids.id(ObjectKey::AnimationStack(animation_index)))
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct(&format!("AnimStack::{}", clip.name))
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct("")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                drop(attrs);
+
+                writer.new_node("Properties70")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                write_property_i64(writer, "LocalStart", "KTime", "Time", "", clip.start)?;
+                write_property_i64(writer, "LocalStop", "KTime", "Time", "", clip.stop)?;
+                write_property_i64(writer, "ReferenceStart", "KTime", "Time", "", clip.start)?;
+                write_property_i64(writer, "ReferenceStop", "KTime", "Time", "", clip.stop)?;
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // Close Properties70
+
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End AnimationStack
+            }
+
+            {
+                let mut attrs = writer.new_node("AnimationLayer")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_i64(ids.id(ObjectKey::AnimationLayer(animation_index)))
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct("AnimLayer::BaseLayer")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct("")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                drop(attrs);
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End AnimationLayer
+            }
+
+            for curve_node in clip.curve_nodes.iter().filter(|c| exported[c.node]) {
+                let mut attrs = writer.new_node("AnimationCurveNode")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_i64(ids.id(curve_node.key))
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct(&format!("AnimCurveNode::{}", curve_node.name))
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                attrs.append_string_direct("")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                drop(attrs);
+
+                writer.new_node("Properties70")
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                for (channel, default, _) in &curve_node.curves {
+                    write_property_f64(writer, channel, "Number", "", "A", &[*default])?;
+                }
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // Close Properties70
+
+                writer.close_node()
+                    .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End AnimationCurveNode
+
+                for (_, default, curve) in &curve_node.curves {
+                    let mut attrs = writer.new_node("AnimationCurve")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_i64(ids.id(curve.key))
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_string_direct("AnimCurve::")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    attrs.append_string_direct("")
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+                    drop(attrs);
+
+                    write_f64_values_node(writer, "Default", &[*default])?;
+                    write_i32_node(writer, "KeyVer", 4008)?;
+                    write_i64_array_node(writer, "KeyTime", curve.times.iter().copied())?;
+                    write_f32_array_node(writer, "KeyValueFloat", curve.values.iter().copied())?;
+                    let (flags, data, ref_counts) = curve_key_attributes(curve);
+                    write_i32_array_node(writer, "KeyAttrFlags", flags)?;
+                    write_f32_array_node(writer, "KeyAttrDataFloat", data)?;
+                    write_i32_array_node(writer, "KeyAttrRefCount", ref_counts)?;
+
+                    writer.close_node()
+                        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End AnimationCurve
+                }
+            }
+        }
+
+        writer.close_node()
+            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Objects
+    }
+
+    // Write Connections node
+    {
+        writer.new_node("Connections")
+            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+            
+        let is_joint = joint_nodes(gltf);
+        for node in gltf.nodes().filter(|n| exported[n.index()]) {
+            if has_attribute_model(&node, &is_joint) {
+                let attribute_model = ids.id(ObjectKey::AttributeModel(node.index()));
+                write_connection(writer, ids.id(ObjectKey::NodeAttribute(node.index())), attribute_model, None)?;
+                write_connection(writer, attribute_model, ids.id(ObjectKey::Model(node.index())), None)?;
+            } else if is_joint[node.index()] || node.camera().is_some() || node.light().is_some() {
+                write_connection(writer, ids.id(ObjectKey::NodeAttribute(node.index())), ids.id(ObjectKey::Model(node.index())), None)?;
+            }
+
+            if let (Some(mesh), Some(owner)) = (node.mesh(), owners[node.index()]) {
+                // Expanded instances take the Geometry; the node itself only places them
+                let mesh_models: Vec<i64> = match &instances[node.index()] {
+                    Some(transforms) if args.instancing == InstancingMode::Expand => (0..transforms.len())
+                        .map(|instance| ids.id(ObjectKey::InstanceModel(node.index(), instance)))
+                        .collect(),
+                    _ => vec![ids.id(ObjectKey::Model(node.index()))],
+                };
+                for &mesh_model in &mesh_models {
+                    write_connection(writer, ids.id(ObjectKey::Geometry(owner)), mesh_model, None)?;
+
+                    // Connection order defines the indices used by LayerElementMaterial
+                    for material in mesh_materials(&mesh) {
+                        write_connection(writer, ids.id(ObjectKey::Material(material)), mesh_model, None)?;
+                    }
+                    if mesh_model != ids.id(ObjectKey::Model(node.index())) {
+                        write_connection(writer, mesh_model, ids.id(ObjectKey::Model(node.index())), None)?;
+                    }
+                }
+            }
+
+            // Parent/child links
+            for child in node.children() {
+                write_connection(writer, ids.id(ObjectKey::Model(child.index())), ids.id(ObjectKey::Model(node.index())), None)?;
+            }
+        }
+
+        // Textures plug into material properties; images into their textures
+        let mut connected_textures: Vec<usize> = Vec::new();
+        for material in &used_materials {
+            for (texture, _, property) in material_textures(material) {
+                write_connection(writer, ids.id(ObjectKey::Texture(texture.index())), ids.id(ObjectKey::Material(material.index())), Some(property))?;
+                if !connected_textures.contains(&texture.index()) {
+                    connected_textures.push(texture.index());
+                    write_connection(writer, ids.id(ObjectKey::Video(texture.source().index())), ids.id(ObjectKey::Texture(texture.index())), None)?;
+                }
+            }
+        }
+
+        // Skin -> Geometry, Cluster -> Skin, joint Model -> Cluster
+        for (owner, _, skin) in &geometries {
+            let Some(skin) = skin else {
+                continue;
+            };
+            let skin_id = ids.id(ObjectKey::SkinDeformer(*owner));
+            write_connection(writer, skin_id, ids.id(ObjectKey::Geometry(*owner)), None)?;
+            for (slot, joint) in skin.joints().enumerate() {
+                write_connection(writer, ids.id(ObjectKey::Cluster(*owner, slot)), skin_id, None)?;
+                write_connection(writer, ids.id(ObjectKey::Model(joint.index())), ids.id(ObjectKey::Cluster(*owner, slot)), None)?;
+            }
+        }
+
+        // BlendShape -> Geometry, BlendShapeChannel -> BlendShape, Shape -> BlendShapeChannel
+        for (owner, mesh, _) in &geometries {
+            let target_count = mesh_morph_target_count(mesh);
+            if target_count == 0 {
+                continue;
+            }
+            write_connection(writer, ids.id(ObjectKey::BlendShape(*owner)), ids.id(ObjectKey::Geometry(*owner)), None)?;
+            for target in 0..target_count {
+                write_connection(writer, ids.id(ObjectKey::BlendShapeChannel(*owner, target)), ids.id(ObjectKey::BlendShape(*owner)), None)?;
+                write_connection(writer, ids.id(ObjectKey::ShapeGeometry(*owner, target)), ids.id(ObjectKey::BlendShapeChannel(*owner, target)), None)?;
+            }
+        }
+
+        // Layer -> Stack, CurveNode -> Layer and animated property, Curve -> CurveNode component
+        for (animation_index, clip) in animations.iter().enumerate() {
+            let layer_id = ids.id(ObjectKey::AnimationLayer(animation_index));
+            write_connection(writer, layer_id, ids.id(ObjectKey::AnimationStack(animation_index)), None)?;
+            for curve_node in clip.curve_nodes.iter().filter(|c| exported[c.node]) {
+                write_connection(writer, ids.id(curve_node.key), layer_id, None)?;
+                write_connection(writer, ids.id(curve_node.key), ids.id(curve_node.target), Some(curve_node.property))?;
+                for (channel, _, curve) in &curve_node.curves {
+                    write_connection(writer, ids.id(curve.key), ids.id(curve_node.key), Some(channel))?;
+                }
+            }
+        }
+
+        // Scene roots hang off the FBX root node (ID 0), as do joints pulled in from outside the scene
+        let parents = node_parents(gltf);
+        for node in gltf.nodes().filter(|n| exported[n.index()]) {
+            if parents[node.index()].is_none_or(|parent| !exported[parent]) {
+                write_connection(writer, ids.id(ObjectKey::Model(node.index())), 0, None)?;
+            }
+        }
+        
+        writer.close_node()
+            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Connections
+    }
+
+    Ok(())
+}
+
+// What an FBX object stands for. A Geometry and its deformers take the index of the node
+// owning them (see geometry_owners); glTF's default material has no index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum ObjectKey {
+    Geometry(usize),
+    Model(usize),
+    // Expanded EXT_mesh_gpu_instancing instances get a Model each: (node, instance)
+    InstanceModel(usize, usize),
+    // The child Model holding a node's camera or light (see has_attribute_model)
+    AttributeModel(usize),
+    // A node carries at most one NodeAttribute (skeleton, camera, light, ...)
+    NodeAttribute(usize),
+    Material(Option<usize>),
+    Video(usize),
+    Texture(usize),
+    // All skinned meshes share one bind pose
+    BindPose,
+    // Skins are written per skinned Geometry, clusters per joint slot of that skin: (owner, slot)
+    SkinDeformer(usize),
+    Cluster(usize, usize),
+    BlendShape(usize),
+    // Morph targets get a BlendShapeChannel and a Shape geometry each: (owner, target)
+    BlendShapeChannel(usize, usize),
+    ShapeGeometry(usize, usize),
+    AnimationStack(usize),
+    AnimationLayer(usize),
+    // One curve node per animation channel, or per morph target for weights channels:
+    // (animation, channel, part), and one curve per component of it
+    AnimationCurveNode(usize, usize, usize),
+    AnimationCurve(usize, usize, usize, usize),
+}
+
+// Hands out FBX object IDs in order of first use, so no two objects can ever share one
+// however many nodes, targets or instances there are. The Objects and Connections
+// writers ask for the same keys and so get the same IDs.
+#[derive(Default)]
+struct ObjectIds {
+    ids: std::collections::HashMap<ObjectKey, i64>,
+}
+
+impl ObjectIds {
+    fn id(&mut self, key: ObjectKey) -> i64 {
+        // 0 is the FBX root node
+        let next = 1_000_000 + self.ids.len() as i64;
+        *self.ids.entry(key).or_insert(next)
+    }
+}
+
+// Textures a material uses, with their UV set and the FBX material property they feed
+fn material_textures<'a>(material: &gltf::Material<'a>) -> Vec<(gltf::Texture<'a>, u32, &'static str)> {
+    let mut textures = Vec::new();
+    if let Some(info) = material.pbr_metallic_roughness().base_color_texture() {
+        textures.push((info.texture(), info.tex_coord(), "DiffuseColor"));
+    }
+    if let Some(info) = material.normal_texture() {
+        textures.push((info.texture(), info.tex_coord(), "NormalMap"));
+    }
+    if let Some(info) = material.emissive_texture() {
+        textures.push((info.texture(), info.tex_coord(), "EmissiveColor"));
+    }
+    textures
+}
+
+fn uv_set_name(set: usize) -> String {
+    if set == 0 {
+        "UVMap".to_string()
+    } else {
+        format!("UVMap{}", set)
+    }
+}
+
+// Distinct materials of a mesh's triangle primitives, in order of first use
+fn mesh_materials(mesh: &gltf::Mesh) -> Vec<Option<usize>> {
+    let mut materials = Vec::new();
+    for primitive in mesh.primitives().filter(|p| is_triangle_mode(p.mode())) {
+        let index = primitive.material().index();
+        if !materials.contains(&index) {
+            materials.push(index);
+        }
+    }
+    materials
+}
+
+// The node whose Geometry each node (by index) with a mesh uses. Shared Geometry belongs to
+// the first node with that mesh and skin, as a Skin deformer binds the Geometry to one skeleton;
+// duplicated and collapsed-instance Geometry belongs to every node itself. So does the Geometry
+// of a node overriding or animating its morph weights, as those belong to the Geometry's
+// BlendShapeChannels.
+fn geometry_owners(gltf: &Document, args: &Args) -> Vec<Option<usize>> {
+    let mut own_weights: Vec<bool> = gltf.nodes().map(|node| node.weights().is_some()).collect();
+    for channel in gltf.animations().flat_map(|animation| animation.channels()) {
+        if channel.target().property() == gltf::animation::Property::MorphTargetWeights {
+            own_weights[channel.target().node().index()] = true;
+        }
+    }
+
+    let mut first_users: std::collections::HashMap<(usize, Option<usize>), usize> = std::collections::HashMap::new();
+    gltf.nodes()
+        .map(|node| {
+            let mesh = node.mesh()?;
+            // Collapsed instances are baked into a Geometry of the node's own
+            let collapsed = args.instancing == InstancingMode::Collapse
+                && node.extension_value(EXT_MESH_GPU_INSTANCING).is_some();
+            Some(match args.geometry {
+                _ if collapsed || own_weights[node.index()] => node.index(),
+                GeometryMode::Shared => *first_users
+                    .entry((mesh.index(), node.skin().map(|skin| skin.index())))
+                    .or_insert(node.index()),
+                GeometryMode::Duplicate => node.index(),
+            })
+        })
+        .collect()
+}
+
+// Rest weights of a node's morph targets: the node's own, else the mesh's
+fn morph_default_weights<'a>(node: &gltf::Node<'a>, mesh: &gltf::Mesh<'a>) -> &'a [f32] {
+    node.weights().or(mesh.weights()).unwrap_or(&[])
+}
+
+// The Model type and view direction correction of a node's camera or light, unless the node is a
+// joint and keeps its skeleton attribute. glTF cameras and lights look down -Z; FBX cameras look
+// down +X, FBX lights down -Y.
+fn view_attribute(node: &gltf::Node, is_joint: &[bool]) -> Option<(&'static str, [f64; 3])> {
+    if is_joint[node.index()] {
+        None
+    } else if node.camera().is_some() {
+        Some(("Camera", [0.0, -90.0, 0.0]))
+    } else if node.light().is_some() {
+        Some(("Light", [-90.0, 0.0, 0.0]))
+    } else {
+        None
+    }
+}
+
+// Whether the node's camera or light goes on a child Model of its own. The correction is a
+// PostRotation, which would also turn the node's mesh and children.
+fn has_attribute_model(node: &gltf::Node, is_joint: &[bool]) -> bool {
+    view_attribute(node, is_joint).is_some() && (node.mesh().is_some() || node.children().next().is_some())
+}
+
+// (owner node, mesh, skin) for every Geometry used by the exported nodes, by owner.
+// The owner itself may belong to another scene; only its index is used for IDs.
+fn exported_geometries<'a>(
+    gltf: &'a Document,
+    exported: &[bool],
+    owners: &[Option<usize>],
+) -> Vec<(usize, gltf::Mesh<'a>, Option<gltf::Skin<'a>>)> {
+    let mut geometries: Vec<(usize, gltf::Mesh, Option<gltf::Skin>)> = Vec::new();
+    let mut seen_owners: std::collections::HashSet<usize> = std::collections::HashSet::new();
+    for node in gltf.nodes().filter(|n| exported[n.index()]) {
+        if let (Some(mesh), Some(owner)) = (node.mesh(), owners[node.index()])
+            && seen_owners.insert(owner)
+        {
+            geometries.push((owner, mesh, node.skin()));
+        }
+    }
+    geometries.sort_by_key(|&(owner, _, _)| owner);
+    geometries
+}
+
+const EXT_MESH_GPU_INSTANCING: &str = "EXT_mesh_gpu_instancing";
+
+// (translation, rotation, scale) of one EXT_mesh_gpu_instancing instance
+type InstanceTransform = ([f32; 3], [f32; 4], [f32; 3]);
+
+// Per-instance transforms from the node's EXT_mesh_gpu_instancing attributes, missing ones
+// being identity. None without the extension, for skinned meshes (the extension has no way
+// to instance skeletons) and for accessors of the wrong type.
+fn node_instances(gltf: &Document, node: &gltf::Node, buffers: &[gltf::buffer::Data]) -> Option<Vec<InstanceTransform>> {
+    let attributes = node.extension_value(EXT_MESH_GPU_INSTANCING)?.get("attributes")?;
+    if node.mesh().is_none() || node.skin().is_some() {
+        return None;
+    }
+    let accessor = |name: &str| {
+        let index = attributes.get(name)?.as_u64()?;
+        gltf.accessors().nth(index as usize)
+    };
+    let get_buffer_data = |buffer: gltf::Buffer| buffers.get(buffer.index()).map(|data| &data[..]);
+    let read_vec3 = |name: &str| -> Option<Option<Vec<[f32; 3]>>> {
+        let Some(accessor) = accessor(name) else {
+            return Some(None);
+        };
+        if accessor.data_type() != gltf::accessor::DataType::F32 || accessor.dimensions() != gltf::accessor::Dimensions::Vec3 {
+            return None;
+        }
+        Some(Some(gltf::accessor::Iter::<[f32; 3]>::new(accessor, get_buffer_data)?.collect()))
+    };
+
+    let translations = read_vec3("TRANSLATION")?;
+    let scales = read_vec3("SCALE")?;
+    // Rotations may also be normalized signed bytes or shorts, which are only roughly unit length
+    let rotations: Option<Vec<[f32; 4]>> = match accessor("ROTATION") {
+        None => None,
+        Some(accessor) if accessor.dimensions() != gltf::accessor::Dimensions::Vec4 => return None,
+        Some(accessor) => Some(match accessor.data_type() {
+            gltf::accessor::DataType::F32 => gltf::accessor::Iter::<[f32; 4]>::new(accessor, get_buffer_data)?.collect(),
+            gltf::accessor::DataType::I8 => gltf::accessor::Iter::<[i8; 4]>::new(accessor, get_buffer_data)?
+                .map(|q| q.map(|c| (c as f32 / 127.0).max(-1.0)))
+                .collect(),
+            gltf::accessor::DataType::I16 => gltf::accessor::Iter::<[i16; 4]>::new(accessor, get_buffer_data)?
+                .map(|q| q.map(|c| (c as f32 / 32767.0).max(-1.0)))
+                .collect(),
+            _ => return None,
+        }),
+    };
+    let rotations = rotations.map(|values| {
+        values.into_iter()
+            .map(|q| {
+                let length = q.iter().map(|c| c * c).sum::<f32>().sqrt();
+                if length > f32::EPSILON { q.map(|c| c / length) } else { [0.0, 0.0, 0.0, 1.0] }
+            })
+            .collect::<Vec<_>>()
+    });
+
+    // Every attribute holds one value per instance
+    let count = [translations.as_ref().map(Vec::len), rotations.as_ref().map(Vec::len), scales.as_ref().map(Vec::len)]
+        .into_iter()
+        .flatten()
+        .min()?;
+    Some((0..count)
+        .map(|instance| (
+            translations.as_ref().map_or([0.0; 3], |values| values[instance]),
+            rotations.as_ref().map_or([0.0, 0.0, 0.0, 1.0], |values| values[instance]),
+            scales.as_ref().map_or([1.0; 3], |values| values[instance]),
+        ))
+        .collect())
+}
+
+// Scales, then rotates a direction or offset into the instance's space
+fn instance_vector((_, rotation, scale): &InstanceTransform, v: [f32; 3]) -> [f32; 3] {
+    quat_rotate(*rotation, [v[0] * scale[0], v[1] * scale[1], v[2] * scale[2]])
+}
+
+fn instance_point(instance: &InstanceTransform, p: [f32; 3]) -> [f32; 3] {
+    let (translation, _, _) = instance;
+    let v = instance_vector(instance, p);
+    [v[0] + translation[0], v[1] + translation[1], v[2] + translation[2]]
+}
+
+// Normals take the inverse transpose, i.e. the inverse scale; the result is not renormalized
+fn instance_normal((_, rotation, scale): &InstanceTransform, n: [f32; 3]) -> [f32; 3] {
+    quat_rotate(*rotation, [n[0] / scale[0], n[1] / scale[1], n[2] / scale[2]])
+}
+
+fn instance_tangent(instance: &InstanceTransform, t: [f32; 4]) -> [f32; 4] {
+    let v = normalize(instance_vector(instance, [t[0], t[1], t[2]])).unwrap_or([t[0], t[1], t[2]]);
+    let w = if instance_mirrors(instance) { -t[3] } else { t[3] };
+    [v[0], v[1], v[2], w]
+}
+
+// Whether the instance's scale mirrors the mesh
+fn instance_mirrors((_, _, scale): &InstanceTransform) -> bool {
+    scale[0] * scale[1] * scale[2] < 0.0
+}
+
+// Display length of a bone: the distance to its first child joint, else its
+// parent bone's length, else one unit
+fn bone_length(node: &gltf::Node, is_joint: &[bool], parents: &[Option<usize>], gltf: &Document) -> f64 {
+    let child_distance = |node: &gltf::Node| {
+        node.children()
+            .find(|child| is_joint[child.index()])
+            .map(|child| {
+                let (t, _, _) = child.transform().decomposed();
+                dot(t, t).sqrt() as f64
+            })
+            .filter(|&length| length > 1e-6)
+    };
+
+    child_distance(node)
+        .or_else(|| {
+            parents[node.index()]
+                .and_then(|parent| gltf.nodes().nth(parent))
+                .and_then(|parent| child_distance(&parent))
+        })
+        .unwrap_or(1.0)
+}
+
+// One inverse bind matrix per joint slot; glTF defaults missing ones to identity
+fn skin_inverse_bind_matrices(skin: &gltf::Skin, buffers: &[gltf::buffer::Data]) -> Vec<[f64; 16]> {
+    let mut matrices: Vec<[f64; 16]> = skin.reader(|buffer| Some(&buffers[buffer.index()]))
+        .read_inverse_bind_matrices()
+        .map(|iter| iter.map(mat4_from_gltf).collect())
+        .unwrap_or_default();
+    matrices.resize(skin.joints().count(), MAT4_IDENTITY);
+    matrices
+}
+
+// Per-vertex (joint slot, weight) influences of a mesh, in the same vertex
+// order as its Geometry. Joint slots index into the skin's joint list.
+// All JOINTS_n/WEIGHTS_n sets are merged, zero weights dropped, the strongest
+// `max_influences` kept and the weights normalized. Also returns how many
+// vertices lost influences to the cap.
+fn mesh_vertex_influences(
+    mesh: &gltf::Mesh,
+    buffers: &[gltf::buffer::Data],
+    max_influences: Option<u32>,
+) -> (Vec<Vec<(usize, f32)>>, usize) {
+    let mut influences: Vec<Vec<(usize, f32)>> = Vec::new();
+
+    for primitive in geometry_primitives(mesh, buffers) {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
+
+        let start = influences.len();
+        influences.resize(start + positions.len(), Vec::new());
+
+        let mut set = 0;
+        while let (Some(joints), Some(weights)) = (reader.read_joints(set), reader.read_weights(set)) {
+            for (vertex, (joints, weights)) in joints.into_u16().zip(weights.into_f32()).enumerate() {
+                let vertex_influences = &mut influences[start + vertex];
+                for (&joint, &weight) in joints.iter().zip(&weights) {
+                    if weight <= 0.0 {
+                        continue;
+                    }
+                    // The same joint may appear in several sets
+                    match vertex_influences.iter_mut().find(|(j, _)| *j == joint as usize) {
+                        Some((_, w)) => *w += weight,
+                        None => vertex_influences.push((joint as usize, weight)),
+                    }
+                }
+            }
+            set += 1;
+        }
+    }
+
+    let mut clamped_vertices = 0;
+    for vertex_influences in influences.iter_mut() {
+        vertex_influences.sort_by(|a, b| b.1.total_cmp(&a.1));
+        if let Some(max) = max_influences
+            && vertex_influences.len() > max as usize
+        {
+            vertex_influences.truncate(max as usize);
+            clamped_vertices += 1;
+        }
+
+        let total: f32 = vertex_influences.iter().map(|(_, w)| w).sum();
+        if total > 0.0 {
+            for (_, w) in vertex_influences.iter_mut() {
+                *w /= total;
+            }
+        }
+    }
+
+    (influences, clamped_vertices)
+}
+
+// Sparse morph target deltas over the Geometry's vertices (triangle primitives concatenated)
+struct MorphShape {
+    indexes: Vec<i32>,
+    position_deltas: Vec<[f32; 3]>,
+    normal_deltas: Vec<[f32; 3]>,
+}
+
+fn mesh_morph_target_count(mesh: &gltf::Mesh) -> usize {
+    mesh.primitives()
+        .filter(|p| is_triangle_mode(p.mode()))
+        .map(|p| p.morph_targets().count())
+        .max()
+        .unwrap_or(0)
+}
+
+// With collapsed instances the mesh's vertices repeat once per instance, and so do the shapes
+fn mesh_morph_targets(
+    mesh: &gltf::Mesh,
+    buffers: &[gltf::buffer::Data],
+    baked_instances: &[InstanceTransform],
+) -> Vec<MorphShape> {
+    let mut shapes: Vec<MorphShape> = (0..mesh_morph_target_count(mesh))
+        .map(|_| MorphShape { indexes: Vec::new(), position_deltas: Vec::new(), normal_deltas: Vec::new() })
+        .collect();
+    let mut vertex_offset = 0;
+
+    for primitive in geometry_primitives(mesh, buffers) {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
+        let vertex_count = positions.len();
+
+        for (shape, (positions, normals, _)) in shapes.iter_mut().zip(reader.read_morph_targets()) {
+            let positions: Vec<[f32; 3]> = positions.map(|iter| iter.collect()).unwrap_or_default();
+            let normals: Vec<[f32; 3]> = normals.map(|iter| iter.collect()).unwrap_or_default();
+
+            // Only vertices the target actually moves are listed
+            for vertex in 0..vertex_count {
+                let position = positions.get(vertex).copied().unwrap_or([0.0; 3]);
+                let normal = normals.get(vertex).copied().unwrap_or([0.0; 3]);
+                if position.iter().chain(&normal).all(|&v| v == 0.0) {
+                    continue;
+                }
+                shape.indexes.push((vertex_offset + vertex) as i32);
+                shape.position_deltas.push(position);
+                shape.normal_deltas.push(normal);
+            }
+        }
+
+        vertex_offset += vertex_count;
+    }
+
+    if baked_instances.is_empty() {
+        return shapes;
+    }
+    shapes.into_iter()
+        .map(|shape| MorphShape {
+            indexes: (0..baked_instances.len())
+                .flat_map(|copy| shape.indexes.iter().map(move |&index| index + (copy * vertex_offset) as i32))
+                .collect(),
+            position_deltas: baked_instances.iter()
+                .flat_map(|instance| shape.position_deltas.iter().map(|&delta| instance_vector(instance, delta)))
+                .collect(),
+            normal_deltas: baked_instances.iter()
+                .flat_map(|instance| shape.normal_deltas.iter().map(|&delta| instance_normal(instance, delta)))
+                .collect(),
+        })
+        .collect()
+}
+
+// Target names follow the common `extras.targetNames` convention, else "Target<n>"
+fn morph_target_names(mesh: &gltf::Mesh, target_count: usize) -> Vec<String> {
+    let names: Vec<String> = mesh.extras().as_ref()
+        .and_then(|raw| gltf::json::deserialize::from_str::<gltf::json::Value>(raw.get()).ok())
+        .and_then(|extras| {
+            let names = extras.get("targetNames")?.as_array()?;
+            Some(names.iter().map(|name| name.as_str().unwrap_or_default().to_string()).collect())
+        })
+        .unwrap_or_default();
+
+    (0..target_count)
+        .map(|target| match names.get(target) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => format!("Target{}", target),
+        })
+        .collect()
+}
+
+fn is_triangle_mode(mode: gltf::mesh::Mode) -> bool {
+    use gltf::mesh::Mode;
+    matches!(mode, Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan)
+}
+
+// What keeps a primitive's vertices out of the Geometry, if anything. Loading checks neither that
+// the indices stay within the vertices nor that every attribute has one value per vertex.
+fn primitive_problem(primitive: &gltf::Primitive, buffers: &[gltf::buffer::Data]) -> Option<String> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let vertex_count = reader.read_positions()?.len();
+
+    for (semantic, accessor) in primitive.attributes() {
+        if accessor.count() != vertex_count {
+            return Some(format!("has {} {} values for {} vertices", accessor.count(), semantic.to_string(), vertex_count));
+        }
+    }
+    for (target, morph_target) in primitive.morph_targets().enumerate() {
+        let accessors = [morph_target.positions(), morph_target.normals(), morph_target.tangents()];
+        if let Some(accessor) = accessors.into_iter().flatten().find(|accessor| accessor.count() != vertex_count) {
+            return Some(format!("has {} values in morph target {} for {} vertices", accessor.count(), target, vertex_count));
+        }
+    }
+    if let Some(indices) = reader.read_indices()
+        && let Some(index) = indices.into_u32().find(|&index| index as usize >= vertex_count)
+    {
+        return Some(format!("indexes vertex {} of {}", index, vertex_count));
+    }
+    None
+}
+
+// The primitives whose vertices make up a mesh's Geometry, in order
+fn geometry_primitives<'a>(mesh: &gltf::Mesh<'a>, buffers: &[gltf::buffer::Data]) -> impl Iterator<Item = gltf::Primitive<'a>> {
+    mesh.primitives().filter(|p| is_triangle_mode(p.mode()) && primitive_problem(p, buffers).is_none())
+}
+
+// Expands strips and fans into a plain triangle list with glTF's winding
+fn triangle_list(mode: gltf::mesh::Mode, indices: Vec<u32>) -> Vec<u32> {
+    use gltf::mesh::Mode;
+    let count = indices.len().saturating_sub(2);
+    match mode {
+        Mode::TriangleStrip => (0..count)
+            .flat_map(|i| {
+                let odd = i % 2;
+                [indices[i], indices[i + 1 + odd], indices[i + 2 - odd]]
+            })
+            .collect(),
+        Mode::TriangleFan => (0..count)
+            .flat_map(|i| [indices[i + 1], indices[i + 2], indices[0]])
+            .collect(),
+        _ => indices,
+    }
+}
+
+// Whether each node (by index) belongs to the scene: everything below its root nodes, plus
+// the joints (and their subtrees) of skins used there. Without a scene, every node that is
+// not some other node's child counts as a root.
+fn exported_nodes(gltf: &Document, scene: Option<&gltf::Scene>) -> Vec<bool> {
+    let mut pending: Vec<gltf::Node> = match scene {
+        Some(scene) => scene.nodes().collect(),
+        None => {
+            let parents = node_parents(gltf);
+            gltf.nodes().filter(|node| parents[node.index()].is_none()).collect()
+        }
+    };
+
+    let mut exported = vec![false; gltf.nodes().count()];
+    while let Some(node) = pending.pop() {
+        if exported[node.index()] {
+            continue;
+        }
+        exported[node.index()] = true;
+        pending.extend(node.children());
+        if let Some(skin) = node.skin() {
+            pending.extend(skin.joints());
+        }
+    }
+    exported
+}
+
+// Parent node index of every node, by node index
+fn node_parents(gltf: &Document) -> Vec<Option<usize>> {
+    let mut parents = vec![None; gltf.nodes().count()];
+    for node in gltf.nodes() {
+        for child in node.children() {
+            parents[child.index()] = Some(node.index());
+        }
+    }
+    parents
+}
+
+// Whether each node (by index) is a joint of some skin
+fn joint_nodes(gltf: &Document) -> Vec<bool> {
+    let mut is_joint = vec![false; gltf.nodes().count()];
+    for skin in gltf.skins() {
+        for joint in skin.joints() {
+            is_joint[joint.index()] = true;
+        }
+    }
+    is_joint
+}
+
+// FBX KTime: 46186158000 ticks per second
+const FBX_TICKS_PER_SECOND: f64 = 46_186_158_000.0;
+
+// KeyAttrFlags bits (FbxAnimCurveDef::EInterpolationType / ETangentMode)
+const KEY_INTERPOLATION_CONSTANT: i32 = 0x0000_0002;
+const KEY_INTERPOLATION_LINEAR: i32 = 0x0000_0004;
+const KEY_INTERPOLATION_CUBIC: i32 = 0x0000_0008;
+const KEY_TANGENT_USER: i32 = 0x0000_0400;
+const KEY_TANGENT_BREAK: i32 = 0x0000_0800;
+
+// A glTF animation resolved into FBX terms, shared by the Objects and Connections writers
+#[derive(Clone)]
+struct AnimationClip {
+    name: String,
+    start: i64,
+    stop: i64,
+    curve_nodes: Vec<AnimationCurveNode>,
+}
+
+// An animated object property ("Lcl Translation", ...) with one curve per component
+#[derive(Clone)]
+struct AnimationCurveNode {
+    key: ObjectKey,
+    // glTF node the channel animates; left out when the node is not exported
+    node: usize,
+    // "T", "R", "S" or "DeformPercent"
+    name: &'static str,
+    target: ObjectKey,
+    property: &'static str,
+    // (component property such as "d|X", default value, curve)
+    curves: Vec<(&'static str, f64, AnimationCurve)>,
+}
+
+#[derive(Clone)]
+struct AnimationCurve {
+    key: ObjectKey,
+    times: Vec<i64>,
+    values: Vec<f32>,
+    // How each key interpolates towards the next one
+    interpolations: Vec<gltf::animation::Interpolation>,
+    // (incoming, outgoing) slope per key in units per second, used by CUBICSPLINE keys
+    slopes: Vec<(f32, f32)>,
+}
+
+// What a channel drives: (curve node name, target object, property, [(component, default)])
+type CurveNodeTarget = (&'static str, ObjectKey, &'static str, Vec<(&'static str, f64)>);
+
+fn seconds_to_fbx_time(seconds: f32) -> i64 {
+    (seconds as f64 * FBX_TICKS_PER_SECOND).round() as i64
+}
+
+fn convert_animation(
+    animation: &gltf::Animation,
+    buffers: &[gltf::buffer::Data],
+    owners: &[Option<usize>],
+    args: &Args,
+    stats: &mut ConversionStats,
+) -> AnimationClip {
+    use gltf::animation::util::ReadOutputs;
+
+    let name = animation.name()
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("Animation{}", animation.index()));
+    let mut start = f32::INFINITY;
+    let mut stop = f32::NEG_INFINITY;
+    let mut curve_nodes = Vec::new();
+
+    for (channel_index, channel) in animation.channels().enumerate() {
+        let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+        let (Some(inputs), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+            continue;
+        };
+        let mut inputs: Vec<f32> = inputs.collect();
+        let mut interpolation = channel.sampler().interpolation();
+        let cubic = interpolation == gltf::animation::Interpolation::CubicSpline;
+
+        let node = channel.target().node();
+        let (translation, rotation, scale) = node.transform().decomposed();
+        let xyz = |defaults: [f64; 3]| vec![("d|X", defaults[0]), ("d|Y", defaults[1]), ("d|Z", defaults[2])];
+
+        // Per key: the value of every component across the targets and, for CUBICSPLINE,
+        // the (in, out) tangents of every component in the same units
+        type Tangents = Vec<(Vec<f64>, Vec<f64>)>;
+        let (targets, keys, tangents): (Vec<CurveNodeTarget>, Vec<Vec<f64>>, Tangents) = match outputs {
+            ReadOutputs::Translations(values) => {
+                let (keys, tangents) = split_cubic_spline(values.map(|v| v.map(|c| c as f64).to_vec()).collect(), cubic);
+                let target = ("T", ObjectKey::Model(node.index()), "Lcl Translation", xyz(translation.map(|v| v as f64)));
+                (vec![target], keys, tangents)
+            }
+            ReadOutputs::Rotations(values) => {
+                let order = args.rotation_order;
+                let (mut quats, mut quat_tangents) = split_cubic_spline(values.into_f32().collect(), cubic);
+
+                // Linear Euler keys only approximate slerp; denser keys get closer
+                if let Some(rate) = args.resample_rotations
+                    && interpolation != gltf::animation::Interpolation::Step
+                {
+                    (inputs, quats) = resample_rotation(&inputs, &quats, &quat_tangents, rate);
+                    quat_tangents.clear();
+                    interpolation = gltf::animation::Interpolation::Linear;
+                }
+
+                // Each key takes the Euler solution closest to the previous one,
+                // so curves never jump by ±180° or a full turn
+                let mut keys: Vec<[f64; 3]> = Vec::with_capacity(quats.len());
+                for &q in &quats {
+                    let euler = quat_to_euler(q, order);
+                    keys.push(match keys.last() {
+                        Some(&previous) => unwrap_euler(previous, euler, order),
+                        None => euler,
+                    });
+                }
+                let tangents = quats.iter().zip(&keys).zip(&quat_tangents)
+                    .map(|((&q, &key), &(in_tangent, out_tangent))| (
+                        quat_euler_slope(q, in_tangent, order, key).to_vec(),
+                        quat_euler_slope(q, out_tangent, order, key).to_vec(),
+                    ))
+                    .collect();
+                let target = ("R", ObjectKey::Model(node.index()), "Lcl Rotation", xyz(quat_to_euler(rotation, order)));
+                (vec![target], keys.iter().map(|key| key.to_vec()).collect(), tangents)
+            }
+            ReadOutputs::Scales(values) => {
+                let (keys, tangents) = split_cubic_spline(values.map(|v| v.map(|c| c as f64).to_vec()).collect(), cubic);
+                let target = ("S", ObjectKey::Model(node.index()), "Lcl Scaling", xyz(scale.map(|v| v as f64)));
+                (vec![target], keys, tangents)
+            }
+            ReadOutputs::MorphTargetWeights(values) => {
+                let target_count = node.mesh().map_or(0, |mesh| mesh_morph_target_count(&mesh));
+                let Some(mesh) = node.mesh().filter(|_| target_count > 0) else {
+                    stats.warnings.push(format!(
+                        "Animation '{}' animates weights of node '{}', which has no morph targets; skipped",
+                        name, node.name().unwrap_or("Node")
+                    ));
+                    continue;
+                };
+
+                // Outputs hold one weight per target for each key (and each CUBICSPLINE tangent);
+                // glTF weights are 0..1, DeformPercent is 0..100
+                let weights: Vec<f32> = values.into_f32().collect();
+                let groups: Vec<Vec<f64>> = weights.chunks_exact(target_count)
+                    .map(|group| group.iter().map(|&weight| weight as f64 * 100.0).collect())
+                    .collect();
+                let (keys, tangents) = split_cubic_spline(groups, cubic);
+
+                let default_weights = morph_default_weights(&node, &mesh);
+                let owner = owners[node.index()].unwrap_or(node.index());
+                let targets = (0..target_count)
+                    .map(|target| {
+                        let default = default_weights.get(target).copied().unwrap_or(0.0) as f64 * 100.0;
+                        let channel = ObjectKey::BlendShapeChannel(owner, target);
+                        ("DeformPercent", channel, "DeformPercent", vec![("d|DeformPercent", default)])
+                    })
+                    .collect();
+                (targets, keys, tangents)
+            }
+        };
+
+        let key_count = inputs.len().min(keys.len());
+        if key_count == 0 {
+            continue;
+        }
+        start = start.min(inputs[0]);
+        stop = stop.max(inputs[key_count - 1]);
+
+        let times: Vec<i64> = inputs[..key_count].iter().map(|&t| seconds_to_fbx_time(t)).collect();
+        let mut component = 0;
+        for (part, (curve_name, target, property, components)) in targets.into_iter().enumerate() {
+            let key = ObjectKey::AnimationCurveNode(animation.index(), channel_index, part);
+            let mut curves = Vec::new();
+            for (offset, (component_name, default)) in components.into_iter().enumerate() {
+                let curve = AnimationCurve {
+                    key: ObjectKey::AnimationCurve(animation.index(), channel_index, part, offset),
+                    times: times.clone(),
+                    values: keys[..key_count].iter().map(|key| key[component] as f32).collect(),
+                    interpolations: vec![interpolation; key_count],
+                    slopes: if tangents.is_empty() {
+                        vec![(0.0, 0.0); key_count]
+                    } else {
+                        tangents.iter().take(key_count)
+                            .map(|(in_tangent, out_tangent)| (in_tangent[component] as f32, out_tangent[component] as f32))
+                            .collect()
+                    },
+                };
+                curves.push((component_name, default, curve));
+                component += 1;
+            }
+
+            curve_nodes.push(AnimationCurveNode {
+                key,
+                node: node.index(),
+                name: curve_name,
+                target,
+                property,
+                curves,
+            });
+        }
+    }
+
+    if curve_nodes.is_empty() {
+        start = 0.0;
+        stop = 0.0;
+    }
+
+    AnimationClip {
+        name,
+        start: seconds_to_fbx_time(start),
+        stop: seconds_to_fbx_time(stop),
+        curve_nodes,
+    }
+}
+
+// Lays the clips end to end on one timeline, one frame (at 30 fps) apart. Curves animating
+// the same property are joined; clips that leave a property alone hold its rest value.
+// Returns the merged clip and each clip's (name, start, stop) on the new timeline
+fn merge_animation_clips(clips: Vec<AnimationClip>, name: &str) -> (AnimationClip, Vec<(String, i64, i64)>) {
+    let gap = (FBX_TICKS_PER_SECOND / 30.0).round() as i64;
+
+    let mut clip_ranges: Vec<(String, i64, i64)> = Vec::new();
+    let mut offset = 0;
+    for clip in &clips {
+        let duration = clip.stop - clip.start;
+        clip_ranges.push((clip.name.clone(), offset, offset + duration));
+        offset += duration + gap;
+    }
+
+    // Every animated (target, property), in order of first appearance
+    let mut properties: Vec<(ObjectKey, &'static str)> = Vec::new();
+    for curve_node in clips.iter().flat_map(|clip| &clip.curve_nodes) {
+        if !properties.contains(&(curve_node.target, curve_node.property)) {
+            properties.push((curve_node.target, curve_node.property));
+        }
+    }
+
+    let mut curve_nodes: Vec<AnimationCurveNode> = Vec::new();
+    for (target, property) in properties {
+        let mut merged: Option<AnimationCurveNode> = None;
+        for (clip, &(_, start, stop)) in clips.iter().zip(&clip_ranges) {
+            let source = clip.curve_nodes.iter()
+                .find(|curve_node| curve_node.target == target && curve_node.property == property);
+            let merged = merged.get_or_insert_with(|| {
+                // The first clip animating the property provides the IDs and rest values
+                let first = clips.iter().flat_map(|clip| &clip.curve_nodes)
+                    .find(|curve_node| curve_node.target == target && curve_node.property == property)
+                    .unwrap();
+                AnimationCurveNode {
+                    key: first.key,
+                    node: first.node,
+                    name: first.name,
+                    target,
+                    property,
+                    curves: first.curves.iter()
+                        .map(|(component, default, curve)| {
+                            let empty = AnimationCurve {
+                                key: curve.key,
+                                times: Vec::new(),
+                                values: Vec::new(),
+                                interpolations: Vec::new(),
+                                slopes: Vec::new(),
+                            };
+                            (*component, *default, empty)
+                        })
+                        .collect(),
+                }
+            });
+
+            for (component, (_, default, curve)) in merged.curves.iter_mut().enumerate() {
+                match source {
+                    Some(source) => {
+                        let shift = start - clip.start;
+                        let source_curve = &source.curves[component].2;
+                        curve.times.extend(source_curve.times.iter().map(|&time| time + shift));
+                        curve.values.extend(&source_curve.values);
+                        curve.interpolations.extend(&source_curve.interpolations);
+                        curve.slopes.extend(&source_curve.slopes);
+                    }
+                    None => {
+                        let times: &[i64] = if stop > start { &[start, stop] } else { &[start] };
+                        for &time in times {
+                            curve.times.push(time);
+                            curve.values.push(*default as f32);
+                            curve.interpolations.push(gltf::animation::Interpolation::Step);
+                            curve.slopes.push((0.0, 0.0));
+                        }
+                    }
+                }
+            }
+        }
+        curve_nodes.extend(merged);
+    }
+
+    let stop = clip_ranges.last().map_or(0, |&(_, _, stop)| stop);
+    let merged = AnimationClip {
+        name: name.to_string(),
+        start: 0,
+        stop,
+        curve_nodes,
+    };
+    (merged, clip_ranges)
+}
+
+// Sidecar CSV listing where each merged clip starts and stops, in seconds
+fn write_clip_list(path: &Path, clip_ranges: &[(String, i64, i64)]) -> Result<()> {
+    let mut csv = String::from("name,start,end\n");
+    for (name, start, stop) in clip_ranges {
+        let name = if name.contains([',', '"', '\n']) {
+            format!("\"{}\"", name.replace('"', "\"\""))
+        } else {
+            name.clone()
+        };
+        csv.push_str(&format!(
+            "{},{:.6},{:.6}\n",
+            name,
+            *start as f64 / FBX_TICKS_PER_SECOND,
+            *stop as f64 / FBX_TICKS_PER_SECOND
+        ));
+    }
+    fs::write(path, csv)
+        .with_context(|| format!("Failed to write clip list {}", path.display()))
+}
+
+// CUBICSPLINE outputs hold (in-tangent, value, out-tangent) per key; other samplers just values
+fn split_cubic_spline<T: Clone>(outputs: Vec<T>, cubic: bool) -> (Vec<T>, Vec<(T, T)>) {
+    if !cubic {
+        return (outputs, Vec::new());
+    }
+    let keys = outputs.chunks_exact(3).map(|key| key[1].clone()).collect();
+    let tangents = outputs.chunks_exact(3).map(|key| (key[0].clone(), key[2].clone())).collect();
+    (keys, tangents)
+}
+
+// Rate of change of the Euler angles (degrees per second) near the key value
+// `reference` while the rotation q moves along the quaternion tangent dq (units per second)
+fn quat_euler_slope(q: [f32; 4], dq: [f32; 4], order: RotationOrder, reference: [f64; 3]) -> [f64; 3] {
+    const STEP: f32 = 1e-3;
+    let euler_at = |t: f32| {
+        let moved: [f32; 4] = std::array::from_fn(|i| q[i] + dq[i] * t);
+        let length = moved.iter().map(|v| v * v).sum::<f32>().sqrt();
+        unwrap_euler(reference, quat_to_euler(moved.map(|v| v / length), order), order)
+    };
+    let (ahead, behind) = (euler_at(STEP), euler_at(-STEP));
+    std::array::from_fn(|axis| (ahead[axis] - behind[axis]) / (2.0 * STEP as f64))
+}
+
+// Samples a LINEAR or CUBICSPLINE rotation sampler at a fixed rate, keeping its own key times
+fn resample_rotation(
+    inputs: &[f32],
+    quats: &[[f32; 4]],
+    tangents: &[([f32; 4], [f32; 4])],
+    rate: u32,
+) -> (Vec<f32>, Vec<[f32; 4]>) {
+    let key_count = inputs.len().min(quats.len());
+    if key_count < 2 {
+        return (inputs[..key_count].to_vec(), quats[..key_count].to_vec());
+    }
+    let (first, last) = (inputs[0], inputs[key_count - 1]);
+
+    let mut times: Vec<f32> = (0..)
+        .map(|frame| first + frame as f32 / rate as f32)
+        .take_while(|&t| t < last)
+        .chain(inputs[..key_count].iter().copied())
+        .collect();
+    times.sort_by(f32::total_cmp);
+    times.dedup_by(|a, b| (*a - *b).abs() < 1e-6);
+
+    let samples = times.iter()
+        .map(|&t| {
+            let key = inputs[..key_count].partition_point(|&input| input <= t).clamp(1, key_count - 1) - 1;
+            let duration = inputs[key + 1] - inputs[key];
+            if duration <= 0.0 {
+                return quats[key];
+            }
+            let u = ((t - inputs[key]) / duration).clamp(0.0, 1.0);
+            if tangents.len() < key_count {
+                return quat_slerp(quats[key], quats[key + 1], u);
+            }
+
+            // glTF cubic Hermite spline on the quaternion components
+            let (u2, u3) = (u * u, u * u * u);
+            let out_tangent = tangents[key].1;
+            let in_tangent = tangents[key + 1].0;
+            let q: [f32; 4] = std::array::from_fn(|i| {
+                (2.0 * u3 - 3.0 * u2 + 1.0) * quats[key][i]
+                    + (u3 - 2.0 * u2 + u) * duration * out_tangent[i]
+                    + (-2.0 * u3 + 3.0 * u2) * quats[key + 1][i]
+                    + (u3 - u2) * duration * in_tangent[i]
+            });
+            let length = q.iter().map(|v| v * v).sum::<f32>().sqrt();
+            q.map(|v| v / length)
+        })
+        .collect();
+
+    (times, samples)
+}
+
+fn quat_slerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let mut cos_theta: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+    // Take the short way around
+    let b = if cos_theta < 0.0 {
+        cos_theta = -cos_theta;
+        b.map(|v| -v)
+    } else {
+        b
+    };
+
+    if cos_theta > 0.9995 {
+        let q: [f32; 4] = std::array::from_fn(|i| a[i] + (b[i] - a[i]) * t);
+        let length = q.iter().map(|v| v * v).sum::<f32>().sqrt();
+        return q.map(|v| v / length);
+    }
+
+    let theta = cos_theta.acos();
+    let weight_a = ((1.0 - t) * theta).sin() / theta.sin();
+    let weight_b = (t * theta).sin() / theta.sin();
+    std::array::from_fn(|i| a[i] * weight_a + b[i] * weight_b)
+}
+
+// KeyAttrFlags / KeyAttrDataFloat / KeyAttrRefCount: key attributes, each shared by
+// a run of RefCount keys. DataFloat is (right slope, next key's left slope, weights, velocity)
+fn curve_key_attributes(curve: &AnimationCurve) -> (Vec<i32>, Vec<f32>, Vec<i32>) {
+    use gltf::animation::Interpolation;
+
+    // Default tangent weights (1/3) packed as two u16 in units of 1/9999
+    let weights = f32::from_bits(0x0D05_0D05);
+    let mut flags: Vec<i32> = Vec::new();
+    let mut data: Vec<f32> = Vec::new();
+    let mut ref_counts: Vec<i32> = Vec::new();
+
+    for (key, &interpolation) in curve.interpolations.iter().enumerate() {
+        let (key_flags, key_data) = match interpolation {
+            Interpolation::Step => (KEY_INTERPOLATION_CONSTANT, [0.0, 0.0, weights, 0.0]),
+            Interpolation::Linear => (KEY_INTERPOLATION_LINEAR, [0.0, 0.0, weights, 0.0]),
+            Interpolation::CubicSpline => {
+                // Hermite tangents with 1/3 weights are exactly the glTF cubic spline
+                let (in_slope, out_slope) = curve.slopes[key];
+                let mut key_flags = KEY_INTERPOLATION_CUBIC | KEY_TANGENT_USER;
+                if (in_slope - out_slope).abs() > 1e-6 {
+                    key_flags |= KEY_TANGENT_BREAK;
+                }
+                let next_in_slope = curve.slopes.get(key + 1).map_or(0.0, |&(slope, _)| slope);
+                (key_flags, [out_slope, next_in_slope, weights, 0.0])
+            }
+        };
+
+        // Consecutive keys with identical attributes share one entry
+        if flags.last() == Some(&key_flags) && data[data.len() - 4..] == key_data {
+            *ref_counts.last_mut().unwrap() += 1;
+        } else {
+            flags.push(key_flags);
+            data.extend(key_data);
+            ref_counts.push(1);
+        }
+    }
+
+    (flags, data, ref_counts)
+}
+
+fn write_i32_node(
+    writer: &mut Writer<BufWriter<fs::File>>,
+    name: &str,
+    value: i32,
+) -> Result<()> {
+    let mut attrs = writer.new_node(name)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    attrs.append_i32(value)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    drop(attrs);
+    writer.close_node()
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    Ok(())
+}
+
+fn write_string_node(
+    writer: &mut Writer<BufWriter<fs::File>>,
+    name: &str,
+    value: &str,
+) -> Result<()> {
+    let mut attrs = writer.new_node(name)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    attrs.append_string_direct(value)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    drop(attrs);
+    writer.close_node()
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    Ok(())
+}
+
+// A node with a few scalar f64 attributes, e.g. ModelUVScaling: 1.0, 1.0
+fn write_f64_values_node(
+    writer: &mut Writer<BufWriter<fs::File>>,
+    name: &str,
+    values: &[f64],
+) -> Result<()> {
+    let mut attrs = writer.new_node(name)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    for &value in values {
+        attrs.append_f64(value)
+            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    }
+    drop(attrs);
+    writer.close_node()
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    Ok(())
+}
+
+fn write_f64_array_node(
+    writer: &mut Writer<BufWriter<fs::File>>,
+    name: &str,
+    values: impl IntoIterator<Item = f64>,
+) -> Result<()> {
+    let mut attrs = writer.new_node(name)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    attrs.append_arr_f64_from_iter(None, values)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    drop(attrs);
+    writer.close_node()
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    Ok(())
+}
+
+fn write_f32_array_node(
+    writer: &mut Writer<BufWriter<fs::File>>,
+    name: &str,
+    values: impl IntoIterator<Item = f32>,
+) -> Result<()> {
+    let mut attrs = writer.new_node(name)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    attrs.append_arr_f32_from_iter(None, values)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    drop(attrs);
+    writer.close_node()
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    Ok(())
+}
+
+fn write_i64_array_node(
+    writer: &mut Writer<BufWriter<fs::File>>,
+    name: &str,
+    values: impl IntoIterator<Item = i64>,
+) -> Result<()> {
+    let mut attrs = writer.new_node(name)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    attrs.append_arr_i64_from_iter(None, values)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    drop(attrs);
+    writer.close_node()
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    Ok(())
+}
+
+fn write_i32_array_node(
+    writer: &mut Writer<BufWriter<fs::File>>,
+    name: &str,
+    values: impl IntoIterator<Item = i32>,
+) -> Result<()> {
+    let mut attrs = writer.new_node(name)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    attrs.append_arr_i32_from_iter(None, values)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    drop(attrs);
+    writer.close_node()
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    Ok(())
+}
+
+// C: "OO", child, parent  or  C: "OP", child, parent, "property"
+fn write_connection(
+    writer: &mut Writer<BufWriter<fs::File>>,
+    child: i64,
+    parent: i64,
+    property: Option<&str>,
+) -> Result<()> {
+    let mut attrs = writer.new_node("C")
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    attrs.append_string_direct(if property.is_some() { "OP" } else { "OO" })
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    attrs.append_i64(child)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    attrs.append_i64(parent)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    if let Some(property) = property {
+        attrs.append_string_direct(property)
+            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    }
+    drop(attrs);
+    writer.close_node()
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    Ok(())
+}
+
+// P: "name", "type", "label", "flags", values...
+fn write_property_f64(
+    writer: &mut Writer<BufWriter<fs::File>>,
+    name: &str,
+    property_type: &str,
+    label: &str,
+    flags: &str,
+    values: &[f64],
+) -> Result<()> {
+    let mut attrs = writer.new_node("P")
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    for text in [name, property_type, label, flags] {
+        attrs.append_string_direct(text)
+            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    }
+    for &value in values {
+        attrs.append_f64(value)
+            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    }
+    drop(attrs);
+    writer.close_node()
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    Ok(())
+}
+
+fn write_property_string(
+    writer: &mut Writer<BufWriter<fs::File>>,
+    name: &str,
+    property_type: &str,
+    label: &str,
+    flags: &str,
+    value: &str,
+) -> Result<()> {
+    let mut attrs = writer.new_node("P")
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    for text in [name, property_type, label, flags, value] {
+        attrs.append_string_direct(text)
+            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    }
+    drop(attrs);
+    writer.close_node()
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    Ok(())
+}
+
+fn write_property_i32(
+    writer: &mut Writer<BufWriter<fs::File>>,
+    name: &str,
+    property_type: &str,
+    label: &str,
+    flags: &str,
+    value: i32,
+) -> Result<()> {
+    let mut attrs = writer.new_node("P")
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    for text in [name, property_type, label, flags] {
+        attrs.append_string_direct(text)
+            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    }
+    attrs.append_i32(value)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    drop(attrs);
+    writer.close_node()
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    Ok(())
+}
+
+fn write_property_i64(
+    writer: &mut Writer<BufWriter<fs::File>>,
+    name: &str,
+    property_type: &str,
+    label: &str,
+    flags: &str,
+    value: i64,
+) -> Result<()> {
+    let mut attrs = writer.new_node("P")
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    for text in [name, property_type, label, flags] {
+        attrs.append_string_direct(text)
+            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    }
+    attrs.append_i64(value)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    drop(attrs);
+    writer.close_node()
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    Ok(())
+}
+
+// Layer: the list of layer elements (by type and typed index) a geometry exposes
+fn write_layer(
+    writer: &mut Writer<BufWriter<fs::File>>,
+    layer_index: i32,
+    elements: &[(&str, i32)],
+) -> Result<()> {
+    let mut attrs = writer.new_node("Layer")
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    attrs.append_i32(layer_index)
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+    drop(attrs);
+
+    write_i32_node(writer, "Version", 100)?;
+
+    for &(element_type, typed_index) in elements {
+        writer.new_node("LayerElement")
+            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?;
+        write_string_node(writer, "Type", element_type)?;
+        write_i32_node(writer, "TypedIndex", typed_index)?;
+        writer.close_node()
+            .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End LayerElement
+    }
+
+    writer.close_node()
+        .map_err(|e| anyhow::anyhow!("FBX write error: {:?}", e))?; // End Layer
+    Ok(())
+}
+
+// Unit normal of the triangle (a, b, c) with counter-clockwise winding
+fn face_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    normalize(cross(sub(b, a), sub(c, a))).unwrap_or([0.0, 0.0, 1.0])
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> Option<[f32; 3]> {
+    let len = dot(v, v).sqrt();
+    if len > f32::EPSILON {
+        Some([v[0] / len, v[1] / len, v[2] / len])
+    } else {
+        None
+    }
+}
+
+// Rotates v by the unit quaternion q (x, y, z, w)
+fn quat_rotate(q: [f32; 4], v: [f32; 3]) -> [f32; 3] {
+    let axis = [q[0], q[1], q[2]];
+    let t = cross(axis, v).map(|c| c * 2.0);
+    let u = cross(axis, t);
+    [v[0] + q[3] * t[0] + u[0], v[1] + q[3] * t[1] + u[1], v[2] + q[3] * t[2] + u[2]]
+}
+
+// Removes the component of `v` along the unit vector `n`
+fn project_onto_plane(v: [f32; 3], n: [f32; 3]) -> [f32; 3] {
+    let d = dot(v, n);
+    [v[0] - n[0] * d, v[1] - n[1] * d, v[2] - n[2] * d]
+}
+
+// The triangle corners of one primitive as MikkTSpace sees them, collecting one tangent per corner
+struct TangentGeometry<'a> {
+    positions: &'a [[f32; 3]],
+    uvs: &'a [[f32; 2]],
+    indices: &'a [u32],
+    corner_normals: &'a [[f32; 3]],
+    tangents: Vec<[f32; 4]>,
+}
+
+impl bevy_mikktspace::Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.positions[self.indices[face * 3 + vert] as usize]
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.corner_normals[face * 3 + vert]
+    }
+
+    // Flip V so the bitangent follows glTF's convention (V points up the texture)
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let uv = self.uvs.get(self.indices[face * 3 + vert] as usize).copied().unwrap_or([0.0, 0.0]);
+        [uv[0], 1.0 - uv[1]]
+    }
+
+    // Corners MikkTSpace leaves out (degenerate triangles with no neighbours) get any tangent
+    fn set_tangent(&mut self, tangent_space: Option<bevy_mikktspace::TangentSpace>, face: usize, vert: usize) {
+        let corner = face * 3 + vert;
+        self.tangents[corner] = match tangent_space {
+            Some(tangent_space) => tangent_space.tangent_encoded(),
+            None => {
+                let t = any_perpendicular(self.corner_normals[corner]);
+                [t[0], t[1], t[2], 1.0]
+            }
+        };
+    }
+}
+
+// MikkTSpace tangents, as glTF asks for when TANGENT is missing. Returns one tangent per corner.
+fn generate_tangents(
+    positions: &[[f32; 3]],
+    uvs: &[[f32; 2]],
+    indices: &[u32],
+    corner_normals: &[[f32; 3]],
+) -> Vec<[f32; 4]> {
+    let mut geometry = TangentGeometry {
+        positions,
+        uvs,
+        indices,
+        corner_normals,
+        tangents: vec![[0.0; 4]; corner_normals.len()],
+    };
+    if bevy_mikktspace::generate_tangents(&mut geometry).is_err() {
+        return vec![[0.0; 4]; corner_normals.len()];
+    }
+    geometry.tangents
+}
+
+// Some unit vector perpendicular to the unit vector `n`
+fn any_perpendicular(n: [f32; 3]) -> [f32; 3] {
+    let axis = if n[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+    normalize(project_onto_plane(axis, n)).unwrap_or([1.0, 0.0, 0.0])
+}
+
+// sRGB transfer function for a linear color channel in [0, 1]
+fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Unit quaternion (x, y, z, w) to FBX Euler angles (X, Y, Z) in degrees.
+// Order XYZ applies X first, i.e. the rotation Rz * Ry * Rx on column vectors
+fn quat_to_euler(q: [f32; 4], order: RotationOrder) -> [f64; 3] {
+    let [x, y, z, w] = q.map(|v| v as f64);
+    // Row-major rotation matrix
+    let m = [
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+        [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x)],
+        [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y)],
+    ];
+
+    let [i, j, k] = order.axes();
+    // Cyclic orders (XYZ, YZX, ZXY) keep the signs, the others flip them
+    let sign = if (j + 3 - i) % 3 == 1 { 1.0 } else { -1.0 };
+
+    // atan2 instead of asin keeps the middle angle precise near ±90°
+    let cos_middle = m[i][i].hypot(m[j][i]);
+    let mut euler = [0.0; 3];
+    euler[j] = (-sign * m[k][i]).atan2(cos_middle);
+    if cos_middle > 1e-6 {
+        euler[i] = (sign * m[k][j]).atan2(m[k][k]);
+        euler[k] = (sign * m[j][i]).atan2(m[i][i]);
+    } else {
+        // Gimbal lock: fold the last rotation into the first
+        euler[i] = (-sign * m[j][k]).atan2(m[j][j]);
+    }
+
+    euler.map(f64::to_degrees)
+}
+
+// The Euler angles equivalent to `euler` that lie closest to `previous`: either the
+// same solution or its twin (a + 180, 180 - b, c + 180), shifted by whole turns
+fn unwrap_euler(previous: [f64; 3], euler: [f64; 3], order: RotationOrder) -> [f64; 3] {
+    let [i, j, k] = order.axes();
+    let mut twin = euler;
+    twin[i] += 180.0;
+    twin[j] = 180.0 - twin[j];
+    twin[k] += 180.0;
+
+    let nearest = |angles: [f64; 3]| -> [f64; 3] {
+        std::array::from_fn(|axis| {
+            angles[axis] + 360.0 * ((previous[axis] - angles[axis]) / 360.0).round()
+        })
+    };
+    let distance = |angles: &[f64; 3]| -> f64 {
+        angles.iter().zip(&previous).map(|(a, b)| (a - b).abs()).sum()
+    };
+
+    let (euler, twin) = (nearest(euler), nearest(twin));
+    if distance(&twin) < distance(&euler) { twin } else { euler }
+}
+
+// 4x4 matrices are column-major [f64; 16], the element order glTF and FBX share
+const MAT4_IDENTITY: [f64; 16] = [
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 1.0, 0.0,
+    0.0, 0.0, 0.0, 1.0,
+];
+
+fn mat4_from_gltf(columns: [[f32; 4]; 4]) -> [f64; 16] {
+    let mut m = [0.0; 16];
+    for (i, value) in columns.iter().flatten().enumerate() {
+        m[i] = *value as f64;
+    }
+    m
+}
+
+fn mat4_invert(m: &[f64; 16]) -> Option<[f64; 16]> {
+    let mut inv = [0.0; 16];
+
+    inv[0] = m[5] * m[10] * m[15] - m[5] * m[11] * m[14] - m[9] * m[6] * m[15]
+        + m[9] * m[7] * m[14] + m[13] * m[6] * m[11] - m[13] * m[7] * m[10];
+    inv[4] = -m[4] * m[10] * m[15] + m[4] * m[11] * m[14] + m[8] * m[6] * m[15]
+        - m[8] * m[7] * m[14] - m[12] * m[6] * m[11] + m[12] * m[7] * m[10];
+    inv[8] = m[4] * m[9] * m[15] - m[4] * m[11] * m[13] - m[8] * m[5] * m[15]
+        + m[8] * m[7] * m[13] + m[12] * m[5] * m[11] - m[12] * m[7] * m[9];
+    inv[12] = -m[4] * m[9] * m[14] + m[4] * m[10] * m[13] + m[8] * m[5] * m[14]
+        - m[8] * m[6] * m[13] - m[12] * m[5] * m[10] + m[12] * m[6] * m[9];
+    inv[1] = -m[1] * m[10] * m[15] + m[1] * m[11] * m[14] + m[9] * m[2] * m[15]
+        - m[9] * m[3] * m[14] - m[13] * m[2] * m[11] + m[13] * m[3] * m[10];
+    inv[5] = m[0] * m[10] * m[15] - m[0] * m[11] * m[14] - m[8] * m[2] * m[15]
+        + m[8] * m[3] * m[14] + m[12] * m[2] * m[11] - m[12] * m[3] * m[10];
+    inv[9] = -m[0] * m[9] * m[15] + m[0] * m[11] * m[13] + m[8] * m[1] * m[15]
+        - m[8] * m[3] * m[13] - m[12] * m[1] * m[11] + m[12] * m[3] * m[9];
+    inv[13] = m[0] * m[9] * m[14] - m[0] * m[10] * m[13] - m[8] * m[1] * m[14]
+        + m[8] * m[2] * m[13] + m[12] * m[1] * m[10] - m[12] * m[2] * m[9];
+    inv[2] = m[1] * m[6] * m[15] - m[1] * m[7] * m[14] - m[5] * m[2] * m[15]
+        + m[5] * m[3] * m[14] + m[13] * m[2] * m[7] - m[13] * m[3] * m[6];
+    inv[6] = -m[0] * m[6] * m[15] + m[0] * m[7] * m[14] + m[4] * m[2] * m[15]
+        - m[4] * m[3] * m[14] - m[12] * m[2] * m[7] + m[12] * m[3] * m[6];
+    inv[10] = m[0] * m[5] * m[15] - m[0] * m[7] * m[13] - m[4] * m[1] * m[15]
+        + m[4] * m[3] * m[13] + m[12] * m[1] * m[7] - m[12] * m[3] * m[5];
+    inv[14] = -m[0] * m[5] * m[14] + m[0] * m[6] * m[13] + m[4] * m[1] * m[14]
+        - m[4] * m[2] * m[13] - m[12] * m[1] * m[6] + m[12] * m[2] * m[5];
+    inv[3] = -m[1] * m[6] * m[11] + m[1] * m[7] * m[10] + m[5] * m[2] * m[11]
+        - m[5] * m[3] * m[10] - m[9] * m[2] * m[7] + m[9] * m[3] * m[6];
+    inv[7] = m[0] * m[6] * m[11] - m[0] * m[7] * m[10] - m[4] * m[2] * m[11]
+        + m[4] * m[3] * m[10] + m[8] * m[2] * m[7] - m[8] * m[3] * m[6];
+    inv[11] = -m[0] * m[5] * m[11] + m[0] * m[7] * m[9] + m[4] * m[1] * m[11]
+        - m[4] * m[3] * m[9] - m[8] * m[1] * m[7] + m[8] * m[3] * m[5];
+    inv[15] = m[0] * m[5] * m[10] - m[0] * m[6] * m[9] - m[4] * m[1] * m[10]
+        + m[4] * m[2] * m[9] + m[8] * m[1] * m[6] - m[8] * m[2] * m[5];
+
+    let det = m[0] * inv[0] + m[1] * inv[4] + m[2] * inv[8] + m[3] * inv[12];
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    Some(inv.map(|v| v / det))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fbxcel::low::v7400::AttributeValue;
+    use fbxcel::tree::any::AnyTree;
+    use fbxcel::tree::v7400::{NodeHandle, Tree};
+
+    fn args(options: &[&str]) -> Args {
+        Args::parse_from(["glb2fbx", "-i", ".", "-o", "."].iter().chain(options))
+    }
+
+    fn f32_bytes(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn u32_bytes(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    // Loads glTF JSON, given without its asset and buffers, over one embedded buffer holding `data`
+    fn load_gltf(json: &str, data: &[u8]) -> (Document, Vec<gltf::buffer::Data>) {
+        let json = format!(
+            r#"{{"asset": {{"version": "2.0"}}, "buffers": [{{"byteLength": {}, "uri": "data:application/octet-stream;base64,{}"}}], {}"#,
+            data.len(),
+            base64::encode(data),
+            json.trim_start().strip_prefix('{').unwrap(),
+        );
+        let root = gltf::json::deserialize::from_str(&json).unwrap();
+        let document = Document::from_json(root).unwrap();
+        let buffers = gltf::import_buffers(&document, None, None).unwrap();
+        (document, buffers)
+    }
+
+    // Converts the first scene (or every root node) and reads the FBX back
+    fn convert(gltf: &Document, buffers: &[gltf::buffer::Data], animations: &[AnimationClip], args: &Args) -> (Tree, ConversionStats) {
+        static NEXT_FILE: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let file = NEXT_FILE.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("glb2fbx_test_{}_{}.fbx", std::process::id(), file));
+
+        let images = load_image_files(gltf, buffers, Path::new("test.glb")).unwrap();
+        let exported = exported_nodes(gltf, gltf.scenes().next().as_ref());
+        let mut writer = Writer::new(BufWriter::new(fs::File::create(&path).unwrap()), FbxVersion::V7_4).unwrap();
+        let mut stats = ConversionStats::default();
+        write_fbx_tree(&mut writer, gltf, buffers, &images, &exported, animations, args, &mut stats).unwrap();
+        writer.finalize(&FbxFooter::default()).unwrap();
+
+        let file = std::io::BufReader::new(fs::File::open(&path).unwrap());
+        let AnyTree::V7400(_, tree, _) = AnyTree::from_seekable_reader(file).unwrap() else {
+            panic!("unexpected FBX version");
+        };
+        fs::remove_file(&path).unwrap();
+        (tree, stats)
+    }
+
+    // Objects of one node name and class, e.g. ("Geometry", "Shape")
+    fn objects<'a>(tree: &'a Tree, name: &str, class: &str) -> Vec<NodeHandle<'a>> {
+        tree.root().first_child_by_name("Objects").unwrap()
+            .children_by_name(name)
+            .filter(|object| matches!(object.attributes().get(2), Some(AttributeValue::String(c)) if c == class))
+            .collect()
+    }
+
+    fn f64_array(node: NodeHandle, name: &str) -> Vec<f64> {
+        match node.first_child_by_name(name).map(|child| child.attributes()) {
+            Some([AttributeValue::ArrF64(values)]) => values.clone(),
+            other => panic!("{} is not an f64 array: {:?}", name, other),
+        }
+    }
+
+    fn string(node: NodeHandle, name: &str) -> String {
+        match node.first_child_by_name(name).map(|child| child.attributes()) {
+            Some([AttributeValue::String(value)]) => value.clone(),
+            other => panic!("{} is not a string: {:?}", name, other),
+        }
+    }
+
+    fn i32_array(node: NodeHandle, name: &str) -> Vec<i32> {
+        match node.first_child_by_name(name).map(|child| child.attributes()) {
+            Some([AttributeValue::ArrI32(values)]) => values.clone(),
+            other => panic!("{} is not an i32 array: {:?}", name, other),
+        }
+    }
+
+    #[test]
+    fn primitives_with_vertices_out_of_range_are_skipped() {
+        let mut data = f32_bytes(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        data.extend(u32_bytes(&[0, 1, 2, 0, 1, 5]));
+        let (gltf, buffers) = load_gltf(r#"{
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+                {"buffer": 0, "byteOffset": 36, "byteLength": 24}
+            ],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0,0,0], "max": [1,1,0]},
+                {"bufferView": 1, "componentType": 5125, "count": 3, "type": "SCALAR"},
+                {"bufferView": 1, "byteOffset": 12, "componentType": 5125, "count": 3, "type": "SCALAR"},
+                {"bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3"}
+            ],
+            "meshes": [{"name": "Tri", "primitives": [
+                {"attributes": {"POSITION": 0}, "indices": 1},
+                {"attributes": {"POSITION": 0}, "indices": 2},
+                {"attributes": {"POSITION": 0, "NORMAL": 3}, "indices": 1}
+            ]}],
+            "nodes": [{"mesh": 0}]
+        }"#, &data);
+
+        let (tree, stats) = convert(&gltf, &buffers, &[], &args(&[]));
+
+        let geometry = objects(&tree, "Geometry", "Mesh")[0];
+        assert_eq!(f64_array(geometry, "Vertices").len(), 9);
+        assert_eq!(i32_array(geometry, "PolygonVertexIndex"), [0, 1, -3]);
+        assert_eq!(stats.warnings, [
+            "Mesh 'Tri' primitive 1 indexes vertex 5 of 3; it was skipped",
+            "Mesh 'Tri' primitive 2 has 2 NORMAL values for 3 vertices; it was skipped",
+        ]);
+    }
+
+    #[test]
+    fn vertex_influences_merge_joint_sets_clamp_and_renormalize() {
+        let mut data = f32_bytes(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        data.extend([0u8, 1, 2, 3, 1, 0, 0, 0, 2, 3, 0, 0]);
+        data.extend(f32_bytes(&[0.4, 0.2, 0.1, 0.1, 1.0, 0.0, 0.0, 0.0, 0.5, 0.5, 0.0, 0.0]));
+        data.extend([4u8, 0, 5, 6, 0, 0, 0, 0, 2, 0, 0, 0]);
+        data.extend(f32_bytes(&[0.05, 0.1, 0.05, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]));
+        let (gltf, buffers) = load_gltf(r#"{
+            "bufferViews": [{"buffer": 0, "byteOffset": 0, "byteLength": 156}],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0,0,0], "max": [1,1,0]},
+                {"bufferView": 0, "byteOffset": 36, "componentType": 5121, "count": 3, "type": "VEC4"},
+                {"bufferView": 0, "byteOffset": 48, "componentType": 5126, "count": 3, "type": "VEC4"},
+                {"bufferView": 0, "byteOffset": 96, "componentType": 5121, "count": 3, "type": "VEC4"},
+                {"bufferView": 0, "byteOffset": 108, "componentType": 5126, "count": 3, "type": "VEC4"}
+            ],
+            "meshes": [{"primitives": [{"attributes": {
+                "POSITION": 0, "JOINTS_0": 1, "WEIGHTS_0": 2, "JOINTS_1": 3, "WEIGHTS_1": 4
+            }}]}]
+        }"#, &data);
+        let mesh = gltf.meshes().next().unwrap();
+
+        let assert_influences = |actual: &[(usize, f32)], expected: &[(usize, f32)]| {
+            assert_eq!(actual.len(), expected.len(), "{:?} != {:?}", actual, expected);
+            for ((joint, weight), (expected_joint, expected_weight)) in actual.iter().zip(expected) {
+                assert_eq!(joint, expected_joint, "{:?} != {:?}", actual, expected);
+                assert!((weight - expected_weight).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+            }
+        };
+
+        // Both sets feed one list, strongest first; zero weights and repeated joints fold away
+        let (influences, clamped_vertices) = mesh_vertex_influences(&mesh, &buffers, None);
+        assert_eq!(clamped_vertices, 0);
+        assert_influences(&influences[0], &[(0, 0.5), (1, 0.2), (2, 0.1), (3, 0.1), (4, 0.05), (5, 0.05)]);
+        assert_influences(&influences[1], &[(1, 1.0)]);
+        assert_influences(&influences[2], &[(2, 0.75), (3, 0.25)]);
+
+        // Only the vertex over the limit is clamped, and what is left still sums to one
+        let (influences, clamped_vertices) = mesh_vertex_influences(&mesh, &buffers, Some(2));
+        assert_eq!(clamped_vertices, 1);
+        assert_influences(&influences[0], &[(0, 0.5 / 0.7), (1, 0.2 / 0.7)]);
+        assert_influences(&influences[1], &[(1, 1.0)]);
+        assert_influences(&influences[2], &[(2, 0.75), (3, 0.25)]);
+    }
+
+    // Builds a glTF whose nodes all instance one morphed triangle, skinned to one joint, plus
+    // a node drawing the triangle unskinned at two EXT_mesh_gpu_instancing instances
+    fn many_node_gltf(node_count: usize) -> (Document, Vec<gltf::buffer::Data>) {
+        let mut data: Vec<u8> = Vec::new();
+        for v in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            data.extend(v.to_le_bytes());
+        }
+        for i in [0u32, 1, 2] {
+            data.extend(i.to_le_bytes());
+        }
+        for v in [0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0] {
+            data.extend(v.to_le_bytes());
+        }
+        data.extend([0u8; 12]);
+        for _ in 0..3 {
+            for w in [1.0f32, 0.0, 0.0, 0.0] {
+                data.extend(w.to_le_bytes());
+            }
+        }
+        for v in [0.0f32, 0.0, 0.0, 2.0, 0.0, 0.0] {
+            data.extend(v.to_le_bytes());
+        }
+
+        // The joint and the instanced node come after the skinned nodes
+        let mut nodes: Vec<String> = (0..node_count)
+            .map(|i| format!(r#"{{"name":"Node{}","mesh":0,"skin":0,"translation":[{},0,0]}}"#, i, i))
+            .collect();
+        nodes.push(r#"{"name":"Joint"}"#.to_string());
+        nodes.push(r#"{"name":"Instanced","mesh":1,"extensions":{"EXT_mesh_gpu_instancing":{"attributes":{"TRANSLATION":5}}}}"#.to_string());
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "extensionsUsed": ["EXT_mesh_gpu_instancing"],
+                "buffers": [{{"byteLength": {}, "uri": "data:application/octet-stream;base64,{}"}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                    {{"buffer": 0, "byteOffset": 36, "byteLength": 12}},
+                    {{"buffer": 0, "byteOffset": 48, "byteLength": 36}},
+                    {{"buffer": 0, "byteOffset": 84, "byteLength": 12}},
+                    {{"buffer": 0, "byteOffset": 96, "byteLength": 48}},
+                    {{"buffer": 0, "byteOffset": 144, "byteLength": 24}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0,0,0], "max": [1,1,0]}},
+                    {{"bufferView": 1, "componentType": 5125, "count": 3, "type": "SCALAR"}},
+                    {{"bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0,0,1], "max": [0,0,1]}},
+                    {{"bufferView": 3, "componentType": 5121, "count": 3, "type": "VEC4"}},
+                    {{"bufferView": 4, "componentType": 5126, "count": 3, "type": "VEC4"}},
+                    {{"bufferView": 5, "componentType": 5126, "count": 2, "type": "VEC3"}}
+                ],
+                "meshes": [{{"primitives": [{{
+                    "attributes": {{"POSITION": 0, "JOINTS_0": 3, "WEIGHTS_0": 4}},
+                    "indices": 1,
+                    "targets": [{{"POSITION": 2}}]
+                }}]}}, {{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1}}]}}],
+                "skins": [{{"joints": [{}]}}],
+                "nodes": [{}],
+                "scenes": [{{"nodes": [{}]}}]
+            }}"#,
+            data.len(),
+            base64::encode(&data),
+            node_count,
+            nodes.join(","),
+            (0..node_count + 2).map(|i| i.to_string()).collect::<Vec<_>>().join(","),
+        );
+
+        let root = gltf::json::deserialize::from_str(&json).unwrap();
+        let document = Document::from_json(root).unwrap();
+        let buffers = gltf::import_buffers(&document, None, None).unwrap();
+        (document, buffers)
+    }
+
+    // The first attribute of every object is its ID
+    fn id(object: NodeHandle) -> i64 {
+        match object.attributes().first() {
+            Some(AttributeValue::I64(id)) => *id,
+            other => panic!("{} has no ID: {:?}", object.name(), other),
+        }
+    }
+
+    // (child, parent) IDs of every connection
+    fn connections(tree: &Tree) -> Vec<(i64, i64)> {
+        tree.root().first_child_by_name("Connections").unwrap()
+            .children_by_name("C")
+            .map(|connection| match connection.attributes() {
+                [_, AttributeValue::I64(child), AttributeValue::I64(parent), ..] => (*child, *parent),
+                other => panic!("malformed connection: {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn object_ids_are_unique() {
+        let (gltf, buffers) = many_node_gltf(3);
+        let (tree, _) = convert(&gltf, &buffers, &[], &args(&["--geometry", "duplicate"]));
+        let ids: Vec<i64> = tree.root().first_child_by_name("Objects").unwrap()
+            .children()
+            .map(id)
+            .collect();
+
+        // Models, Geometries, BlendShapes, channels, Shapes, Skins and Clusters for every node
+        assert!(ids.len() > 7 * 3);
+        let mut unique = ids.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), ids.len(), "duplicate object IDs");
+    }
+
+    #[test]
+    fn morph_targets_become_blend_shapes() {
+        // One triangle drawn twice; target 0 moves vertex 1 and turns vertex 2's normal in the
+        // first primitive, target 1 moves vertex 0 of the second one
+        let mut data = f32_bytes(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        data.extend(u32_bytes(&[0, 1, 2]));
+        data.extend(f32_bytes(&[0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]));
+        data.extend(f32_bytes(&[0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0]));
+        data.extend(f32_bytes(&[0.0; 9]));
+        data.extend(f32_bytes(&[0.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]));
+        let (gltf, buffers) = load_gltf(r#"{
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+                {"buffer": 0, "byteOffset": 36, "byteLength": 12},
+                {"buffer": 0, "byteOffset": 48, "byteLength": 144}
+            ],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0,0,0], "max": [1,1,0]},
+                {"bufferView": 1, "componentType": 5125, "count": 3, "type": "SCALAR"},
+                {"bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0,0,0], "max": [0,0,1]},
+                {"bufferView": 2, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC3"},
+                {"bufferView": 2, "byteOffset": 72, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0,0,0], "max": [0,0,0]},
+                {"bufferView": 2, "byteOffset": 108, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0,0,0], "max": [0,2,0]}
+            ],
+            "meshes": [{
+                "primitives": [
+                    {"attributes": {"POSITION": 0}, "indices": 1, "targets": [{"POSITION": 2, "NORMAL": 3}, {"POSITION": 4}]},
+                    {"attributes": {"POSITION": 0}, "indices": 1, "targets": [{"POSITION": 4}, {"POSITION": 5}]}
+                ],
+                "weights": [0.25, 0.5],
+                "extras": {"targetNames": ["Smile", ""]}
+            }],
+            "nodes": [{"name": "Face", "mesh": 0}, {"name": "Override", "mesh": 0, "weights": [1.0, 0.0]}]
+        }"#, &data);
+
+        let (tree, _) = convert(&gltf, &buffers, &[], &args(&[]));
+        let connections = connections(&tree);
+        let parent = |child: NodeHandle| {
+            let parents: Vec<i64> = connections.iter()
+                .filter(|&&(c, _)| c == id(child))
+                .map(|&(_, parent)| parent)
+                .collect();
+            assert_eq!(parents.len(), 1, "{:?} should have one parent", child.attributes());
+            parents[0]
+        };
+        let name = |object: NodeHandle| match object.attributes().get(1) {
+            Some(AttributeValue::String(name)) => name.clone(),
+            other => panic!("{} has no name: {:?}", object.name(), other),
+        };
+
+        // The node overriding the weights gets a Geometry and BlendShape of its own
+        let models = objects(&tree, "Model", "Mesh");
+        let geometries = objects(&tree, "Geometry", "Mesh");
+        let blend_shapes = objects(&tree, "Deformer", "BlendShape");
+        let channels = objects(&tree, "Deformer", "BlendShapeChannel");
+        let shapes = objects(&tree, "Geometry", "Shape");
+        assert_eq!((geometries.len(), blend_shapes.len(), channels.len(), shapes.len()), (2, 2, 4, 4));
+
+        for (model, expected_percents) in models.iter().zip([[25.0, 50.0], [100.0, 0.0]]) {
+            let geometry = *geometries.iter().find(|&&geometry| parent(geometry) == id(*model)).unwrap();
+            let blend_shape = *blend_shapes.iter().find(|&&blend_shape| parent(blend_shape) == id(geometry)).unwrap();
+            let geometry_channels: Vec<NodeHandle> = channels.iter()
+                .copied()
+                .filter(|&channel| parent(channel) == id(blend_shape))
+                .collect();
+            assert_eq!(
+                geometry_channels.iter().map(|&channel| name(channel)).collect::<Vec<_>>(),
+                ["SubDeformer::Smile", "SubDeformer::Target1"],
+            );
+
+            for (channel, expected_percent) in geometry_channels.iter().zip(expected_percents) {
+                assert_eq!(f64_array(*channel, "FullWeights"), [100.0]);
+                match channel.first_child_by_name("DeformPercent").map(|child| child.attributes()) {
+                    Some([AttributeValue::F64(percent)]) => assert_eq!(*percent, expected_percent),
+                    other => panic!("DeformPercent is not a number: {:?}", other),
+                }
+            }
+
+            // Only moved vertices are listed, indexing the primitives' vertices end to end
+            let shape = |channel: NodeHandle| *shapes.iter().find(|&&shape| parent(shape) == id(channel)).unwrap();
+            let smile = shape(geometry_channels[0]);
+            assert_eq!(name(smile), "Geometry::Smile");
+            assert_eq!(i32_array(smile, "Indexes"), [1, 2]);
+            assert_eq!(f64_array(smile, "Vertices"), [0.0, 0.0, 1.0, 0.0, 0.0, 0.0]);
+            assert_eq!(f64_array(smile, "Normals"), [0.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+            let second = shape(geometry_channels[1]);
+            assert_eq!(i32_array(second, "Indexes"), [3]);
+            assert_eq!(f64_array(second, "Vertices"), [0.0, 2.0, 0.0]);
+            assert_eq!(f64_array(second, "Normals"), [0.0, 0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn tangents_follow_the_normal_texture_uv_set() {
+        let mut data = f32_bytes(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        data.extend(f32_bytes(&[0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0]));
+        // TEXCOORD_0 runs U along +Y, TEXCOORD_1 along +X
+        data.extend(f32_bytes(&[0.0, 0.0, 0.0, 1.0, 1.0, 0.0]));
+        data.extend(f32_bytes(&[0.0, 1.0, 1.0, 1.0, 0.0, 0.0]));
+        let (gltf, buffers) = load_gltf(r#"{
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+                {"buffer": 0, "byteOffset": 36, "byteLength": 36},
+                {"buffer": 0, "byteOffset": 72, "byteLength": 24},
+                {"buffer": 0, "byteOffset": 96, "byteLength": 24}
+            ],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0,0,0], "max": [1,1,0]},
+                {"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3"},
+                {"bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2"},
+                {"bufferView": 3, "componentType": 5126, "count": 3, "type": "VEC2"},
+                {"bufferView": 1, "componentType": 5126, "count": 2, "type": "VEC4"}
+            ],
+            "images": [{"uri": "data:image/png;base64,AA=="}],
+            "textures": [{"source": 0}],
+            "materials": [{"normalTexture": {"index": 0, "texCoord": 1}}],
+            "meshes": [{"name": "Tri", "primitives": [
+                {"attributes": {"POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2, "TEXCOORD_1": 3}, "material": 0},
+                {"attributes": {"POSITION": 0, "NORMAL": 1, "TANGENT": 4}, "material": 0}
+            ]}],
+            "nodes": [{"mesh": 0}]
+        }"#, &data);
+
+        let (tree, stats) = convert(&gltf, &buffers, &[], &args(&[]));
+
+        let geometry = objects(&tree, "Geometry", "Mesh")[0];
+        let tangent_layer = geometry.first_child_by_name("LayerElementTangent").unwrap();
+        assert_eq!(string(tangent_layer, "Name"), uv_set_name(1));
+        for tangent in f64_array(tangent_layer, "Tangents").chunks(3) {
+            assert!((tangent[0] - 1.0).abs() < 1e-6 && tangent[1].abs() < 1e-6, "{:?}", tangent);
+        }
+        assert_eq!(stats.warnings, ["Mesh 'Tri' primitive 1 has 2 TANGENT values for 3 vertices; it was skipped"]);
+    }
+
+    // Applies the rotations about the order's axes one after another, first axis first
+    fn euler_to_quat(euler: [f64; 3], order: RotationOrder) -> [f32; 4] {
+        let mut q = [0.0, 0.0, 0.0, 1.0];
+        for axis in order.axes() {
+            let half = euler[axis].to_radians() / 2.0;
+            let mut r = [0.0, 0.0, 0.0, half.cos()];
+            r[axis] = half.sin();
+            // r * q
+            q = [
+                r[3] * q[0] + r[0] * q[3] + r[1] * q[2] - r[2] * q[1],
+                r[3] * q[1] - r[0] * q[2] + r[1] * q[3] + r[2] * q[0],
+                r[3] * q[2] + r[0] * q[1] - r[1] * q[0] + r[2] * q[3],
+                r[3] * q[3] - r[0] * q[0] - r[1] * q[1] - r[2] * q[2],
+            ];
+        }
+        q.map(|v| v as f32)
+    }
+
+    // q and -q are the same rotation
+    fn assert_same_rotation(a: [f32; 4], b: [f32; 4]) {
+        let dot: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+        assert!(dot.abs() > 1.0 - 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn euler_angles_round_trip_in_every_rotation_order() {
+        for &order in RotationOrder::value_variants() {
+            // Angles by application order; the middle one stays within ±90°, as quat_to_euler's does
+            for angles in [[0.0, 0.0, 0.0], [30.0, -45.0, 60.0], [-120.0, 10.0, 170.0], [5.0, 89.0, -20.0]] {
+                let mut euler = [0.0; 3];
+                for (&axis, angle) in order.axes().iter().zip(angles) {
+                    euler[axis] = angle;
+                }
+                let q = euler_to_quat(euler, order);
+                let back = quat_to_euler(q, order);
+                for axis in 0..3 {
+                    assert!((back[axis] - euler[axis]).abs() < 1e-3, "{:?}: {:?} != {:?}", order, back, euler);
+                }
+                assert_same_rotation(euler_to_quat(back, order), q);
+            }
+
+            // Gimbal lock: with the middle angle at ±90° only the sum or difference of the other two is
+            // defined, so only the rotation has to survive
+            let middle = order.axes()[1];
+            for middle_angle in [90.0, -90.0] {
+                let mut euler = [25.0, 40.0, -35.0];
+                euler[middle] = middle_angle;
+                let q = euler_to_quat(euler, order);
+                let back = quat_to_euler(q, order);
+                assert!((back[middle] - middle_angle).abs() < 1e-2, "{:?}: {:?}", order, back);
+                assert_same_rotation(euler_to_quat(back, order), q);
+            }
+        }
+    }
+
+    #[test]
+    fn euler_angles_unwrap_across_half_turns() {
+        // 190° about X comes out of quat_to_euler as -170°
+        let euler = quat_to_euler(euler_to_quat([190.0, 0.0, 0.0], RotationOrder::Xyz), RotationOrder::Xyz);
+        assert!((euler[0] + 170.0).abs() < 1e-3);
+
+        let unwrapped = unwrap_euler([170.0, 0.0, 0.0], euler, RotationOrder::Xyz);
+        assert!((unwrapped[0] - 190.0).abs() < 1e-3 && unwrapped[1].abs() < 1e-3 && unwrapped[2].abs() < 1e-3, "{:?}", unwrapped);
+
+        // The twin solution wins when it is closer: (a + 180, 180 - b, c + 180)
+        let unwrapped = unwrap_euler([180.0, 170.0, 180.0], [0.0, 10.0, 0.0], RotationOrder::Xyz);
+        assert!((unwrapped[0] - 180.0).abs() < 1e-9 && (unwrapped[1] - 170.0).abs() < 1e-9 && (unwrapped[2] - 180.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn resampled_rotations_follow_slerp() {
+        // A quarter turn about Z over one second, sampled four times a second
+        let half = std::f32::consts::FRAC_PI_4;
+        let quats = [[0.0, 0.0, 0.0, 1.0], [0.0, 0.0, half.sin(), half.cos()]];
+        let (times, samples) = resample_rotation(&[0.0, 1.0], &quats, &[], 4);
+
+        assert_eq!(times, [0.0, 0.25, 0.5, 0.75, 1.0]);
+        for (&t, &q) in times.iter().zip(&samples) {
+            let angle = std::f32::consts::FRAC_PI_2 * t;
+            assert_same_rotation(q, [0.0, 0.0, (angle / 2.0).sin(), (angle / 2.0).cos()]);
+            assert_same_rotation(q, quat_slerp(quats[0], quats[1], t));
+        }
+    }
+
+    fn convert_animations(gltf: &Document, buffers: &[gltf::buffer::Data], args: &Args) -> Vec<AnimationClip> {
+        let owners = geometry_owners(gltf, args);
+        let mut stats = ConversionStats::default();
+        gltf.animations()
+            .map(|animation| convert_animation(&animation, buffers, &owners, args, &mut stats))
+            .collect()
+    }
+
+    #[test]
+    fn cubic_spline_outputs_split_into_keys_and_tangents() {
+        let (keys, tangents) = split_cubic_spline(vec![0, 1, 2, 3, 4, 5], true);
+        assert_eq!(keys, [1, 4]);
+        assert_eq!(tangents, [(0, 2), (3, 5)]);
+
+        let (keys, tangents) = split_cubic_spline(vec![0, 1, 2], false);
+        assert_eq!(keys, [0, 1, 2]);
+        assert!(tangents.is_empty());
+    }
+
+    #[test]
+    fn key_attributes_follow_the_sampler_interpolation() {
+        // Four keys moving X by one per second; the CUBICSPLINE sampler starts with an out-tangent
+        // of 2 and keeps in- and out-tangents of 1 after that
+        let mut data = f32_bytes(&[0.0, 1.0, 2.0, 3.0]);
+        data.extend(f32_bytes(&(0..4).flat_map(|x| [x as f32, 0.0, 0.0]).collect::<Vec<_>>()));
+        data.extend(f32_bytes(&[
+            0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0,
+            1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0,
+            1.0, 0.0, 0.0, 2.0, 0.0, 0.0, 1.0, 0.0, 0.0,
+            1.0, 0.0, 0.0, 3.0, 0.0, 0.0, 1.0, 0.0, 0.0,
+        ]));
+        let (gltf, buffers) = load_gltf(r#"{
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": 16},
+                {"buffer": 0, "byteOffset": 16, "byteLength": 48},
+                {"buffer": 0, "byteOffset": 64, "byteLength": 144}
+            ],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 4, "type": "SCALAR", "min": [0], "max": [3]},
+                {"bufferView": 1, "componentType": 5126, "count": 4, "type": "VEC3"},
+                {"bufferView": 2, "componentType": 5126, "count": 12, "type": "VEC3"}
+            ],
+            "nodes": [{"name": "Mover"}],
+            "animations": [
+                {"samplers": [{"input": 0, "output": 1, "interpolation": "STEP"}], "channels": [{"sampler": 0, "target": {"node": 0, "path": "translation"}}]},
+                {"samplers": [{"input": 0, "output": 1}], "channels": [{"sampler": 0, "target": {"node": 0, "path": "translation"}}]},
+                {"samplers": [{"input": 0, "output": 2, "interpolation": "CUBICSPLINE"}], "channels": [{"sampler": 0, "target": {"node": 0, "path": "translation"}}]}
+            ]
+        }"#, &data);
+
+        let clips = convert_animations(&gltf, &buffers, &args(&[]));
+        let x_curve = |clip: usize| {
+            let (component, _, curve) = &clips[clip].curve_nodes[0].curves[0];
+            assert_eq!(*component, "d|X");
+            curve_key_attributes(curve)
+        };
+        let weights = f32::from_bits(0x0D05_0D05);
+
+        // One attribute entry shared by all four keys
+        assert_eq!(x_curve(0), (vec![KEY_INTERPOLATION_CONSTANT], vec![0.0, 0.0, weights, 0.0], vec![4]));
+        assert_eq!(x_curve(1), (vec![KEY_INTERPOLATION_LINEAR], vec![0.0, 0.0, weights, 0.0], vec![4]));
+
+        // The first key's tangents differ (a broken tangent); the two middle keys share their
+        // slopes; the last key has no next key to take a left slope from
+        let cubic = KEY_INTERPOLATION_CUBIC | KEY_TANGENT_USER;
+        let (_, _, curve) = &clips[2].curve_nodes[0].curves[0];
+        assert_eq!(curve.values, [0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(curve.slopes, [(0.0, 2.0), (1.0, 1.0), (1.0, 1.0), (1.0, 1.0)]);
+        assert_eq!(x_curve(2), (
+            vec![cubic | KEY_TANGENT_BREAK, cubic, cubic],
+            vec![2.0, 1.0, weights, 0.0, 1.0, 1.0, weights, 0.0, 1.0, 0.0, weights, 0.0],
+            vec![1, 2, 1],
+        ));
+    }
+
+    #[test]
+    fn merged_clips_line_up_and_hold_what_they_leave_alone() {
+        // "Walk" moves A along X over 0-1 s; "Jump, high" moves B along Y over 0.5-2.5 s
+        let mut data = f32_bytes(&[0.0, 1.0, 0.5, 2.5]);
+        data.extend(f32_bytes(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0]));
+        data.extend(f32_bytes(&[0.0, 1.0, 0.0, 0.0, 2.0, 0.0]));
+        let (gltf, buffers) = load_gltf(r#"{
+            "bufferViews": [{"buffer": 0, "byteOffset": 0, "byteLength": 64}],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0], "max": [1]},
+                {"bufferView": 0, "byteOffset": 8, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0.5], "max": [2.5]},
+                {"bufferView": 0, "byteOffset": 16, "componentType": 5126, "count": 2, "type": "VEC3"},
+                {"bufferView": 0, "byteOffset": 40, "componentType": 5126, "count": 2, "type": "VEC3"}
+            ],
+            "nodes": [{"name": "A", "translation": [5, 0, 0]}, {"name": "B", "translation": [0, 7, 0]}],
+            "animations": [
+                {"name": "Walk", "samplers": [{"input": 0, "output": 2}], "channels": [{"sampler": 0, "target": {"node": 0, "path": "translation"}}]},
+                {"name": "Jump, high", "samplers": [{"input": 1, "output": 3}], "channels": [{"sampler": 0, "target": {"node": 1, "path": "translation"}}]}
+            ]
+        }"#, &data);
+
+        let clips = convert_animations(&gltf, &buffers, &args(&[]));
+        let (merged, clip_ranges) = merge_animation_clips(clips, "Both");
+
+        // The second clip starts one 30 fps frame after the first one ends
+        let second = FBX_TICKS_PER_SECOND as i64;
+        let gap = (FBX_TICKS_PER_SECOND / 30.0).round() as i64;
+        assert_eq!(clip_ranges, [
+            ("Walk".to_string(), 0, second),
+            ("Jump, high".to_string(), second + gap, 3 * second + gap),
+        ]);
+        assert_eq!((merged.name.as_str(), merged.start, merged.stop), ("Both", 0, 3 * second + gap));
+
+        // Each node keeps its own keys, shifted into its clip's range, and holds its rest
+        // translation with step keys through the other clip
+        let [a, b] = &merged.curve_nodes[..] else {
+            panic!("expected one curve node per node, got {}", merged.curve_nodes.len());
+        };
+        assert_eq!((a.node, a.property, b.node, b.property), (0, "Lcl Translation", 1, "Lcl Translation"));
+        let step = gltf::animation::Interpolation::Step;
+        let linear = gltf::animation::Interpolation::Linear;
+
+        let (_, _, a_x) = &a.curves[0];
+        assert_eq!(a_x.times, [0, second, second + gap, 3 * second + gap]);
+        assert_eq!(a_x.values, [0.0, 1.0, 5.0, 5.0]);
+        assert_eq!(a_x.interpolations, [linear, linear, step, step]);
+
+        let (_, _, b_y) = &b.curves[1];
+        assert_eq!(b_y.times, [0, second, second + gap, 3 * second + gap]);
+        assert_eq!(b_y.values, [7.0, 7.0, 1.0, 2.0]);
+        assert_eq!(b_y.interpolations, [step, step, linear, linear]);
+
+        // The clip list is in seconds, quoting names that hold a comma
+        let path = std::env::temp_dir().join(format!("glb2fbx_test_{}.clips.csv", std::process::id()));
+        write_clip_list(&path, &clip_ranges).unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(csv, "name,start,end\nWalk,0.000000,1.000000\n\"Jump, high\",1.033333,3.033333\n");
+    }
 }